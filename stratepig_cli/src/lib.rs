@@ -1,6 +1,48 @@
 use clap::{App, Arg};
-use log::info;
-use std::default;
+use log::{info, LevelFilter};
+use std::env;
+use std::fmt;
+use std::net::ToSocketAddrs;
+
+mod overrides;
+use overrides::parse_value;
+pub use overrides::{Overrides, ENV_PREFIX};
+
+const DEFAULT_THINK_TIME_MS: u64 = 1000;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 5;
+const DEFAULT_RECONNECT_GRACE_SECS: u64 = 60;
+const DEFAULT_TCP_ADDR: &str = "0.0.0.0:32500";
+const DEFAULT_MAX_ROOMS: usize = 1000;
+const DEFAULT_PRUNE_INTERVAL_SECS: u64 = 180;
+const DEFAULT_MAX_PRUNE_AGE_SECS: u64 = 300;
+const DEFAULT_MAX_USERNAME_LENGTH: usize = 15;
+const DEFAULT_COUNTDOWN_SECS: u64 = 5;
+const DEFAULT_ATTACK_DELAY_SECS: u64 = 4;
+
+/// Environment variable with the path of the config file, if not given as an argument
+const CONFIG_ENV: &str = "STRATEPIG_CONFIG";
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub name: String,
+    pub msg: String,
+}
+
+impl ConfigError {
+    fn new(name: &str, msg: impl Into<String>) -> Self {
+        Self {
+            name: name.to_owned(),
+            msg: msg.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid config '{}': {}", self.name, self.msg)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CliConfig {
@@ -8,52 +50,284 @@ pub struct CliConfig {
     pub swift_game_enter: bool,
     pub ignore_turns: bool,
     pub log_packet_output: bool,
+    pub log_level: LevelFilter,
+    pub config_file: Option<String>,
+    pub modes_dir: Option<String>,
+    pub think_time_ms: u64,
+    /// Seed of the setups and moves of computer players, random if not given
+    pub seed: Option<u64>,
+    /// Iterations the expert computer player stops searching at, instead of the default
+    /// of the search. With a `seed`, only these limit the search and not `think_time_ms`,
+    /// so its moves are the same every game.
+    pub max_iterations: Option<u32>,
+    pub idle_timeout_secs: u64,
+    /// Time between keep alives sent to every client
+    pub heartbeat_interval_secs: u64,
+    /// Time a player in a game has to resume after their connection drops
+    pub reconnect_grace_secs: u64,
+    pub tcp_addr: String,
+    pub ws_addr: Option<String>,
+    pub tls_addr: Option<String>,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub max_rooms: usize,
+    /// Time between checks for rooms that were left unused
+    pub prune_interval_secs: u64,
+    /// Time a room outside of a game can go unused before it is closed
+    pub max_prune_age_secs: u64,
+    pub max_username_length: usize,
+    /// Time between both players being ready in the lobby and the game starting
+    pub countdown_secs: u64,
+    /// Time before the next turn starts after an attack, for the animations to play
+    pub attack_delay_secs: u64,
+}
+
+impl Default for CliConfig {
+    fn default() -> Self {
+        Self {
+            one_player: false,
+            swift_game_enter: false,
+            ignore_turns: false,
+            log_packet_output: false,
+            log_level: LevelFilter::Info,
+            config_file: None,
+            modes_dir: None,
+            think_time_ms: DEFAULT_THINK_TIME_MS,
+            seed: None,
+            max_iterations: None,
+            idle_timeout_secs: DEFAULT_IDLE_TIMEOUT_SECS,
+            heartbeat_interval_secs: DEFAULT_HEARTBEAT_INTERVAL_SECS,
+            reconnect_grace_secs: DEFAULT_RECONNECT_GRACE_SECS,
+            tcp_addr: DEFAULT_TCP_ADDR.to_owned(),
+            ws_addr: None,
+            tls_addr: None,
+            tls_cert: None,
+            tls_key: None,
+            max_rooms: DEFAULT_MAX_ROOMS,
+            prune_interval_secs: DEFAULT_PRUNE_INTERVAL_SECS,
+            max_prune_age_secs: DEFAULT_MAX_PRUNE_AGE_SECS,
+            max_username_length: DEFAULT_MAX_USERNAME_LENGTH,
+            countdown_secs: DEFAULT_COUNTDOWN_SECS,
+            attack_delay_secs: DEFAULT_ATTACK_DELAY_SECS,
+        }
+    }
 }
 
 impl CliConfig {
-    pub fn new() -> Self {
+    /// Builds the config from the defaults, replaced by the config file, then the
+    /// environment and then the arguments, each taking precedence over the one before
+    pub fn new() -> Result<Self, ConfigError> {
         let version = env!("CARGO_PKG_VERSION");
         let authors = env!("CARGO_PKG_AUTHORS");
 
-        let args =
-            App::new("Stratepig Server")
-                .version(version)
-                .author(authors)
-                .arg(
-                    Arg::with_name("ONE_PLAYER").short("p").help(
-                        "If specified, only one player will be required in lobby and in game and turns will be disabled",
-                    ),
-                )
-                .arg(Arg::with_name("SWIFT_GAME_ENTER").short("s").help(
+        let args = App::new("Stratepig Server")
+            .version(version)
+            .author(authors)
+            .arg(
+                Arg::with_name("CONFIG")
+                    .short("c")
+                    .long("config")
+                    .takes_value(true)
+                    .help("TOML file with settings to use instead of the defaults"),
+            )
+            .arg(
+                Arg::with_name("ONE_PLAYER")
+                    .short("p")
+                    .help("If specified, new rooms are played against a computer player"),
+            )
+            .arg(
+                Arg::with_name("SWIFT_GAME_ENTER").short("s").help(
                     "If specified, upon host the player will be sent into a game immediately",
-                ))
-                .arg(
-                    Arg::with_name("IGNORE_TURNS")
-                        .short("t")
-                        .help("If specified, turns will not be used in game"),
-                )
-                .arg(
-                    Arg::with_name("LOG_PACKET_OUTPUT")
+                ),
+            )
+            .arg(
+                Arg::with_name("IGNORE_TURNS")
+                    .short("t")
+                    .help("If specified, turns will not be used in game"),
+            )
+            .arg(
+                Arg::with_name("LOG_PACKET_OUTPUT")
                     .short("o")
-                    .help("If specified, packets received will be logged")
-                )
-                .get_matches();
+                    .help("If specified, packets received will be logged"),
+            )
+            .arg(
+                Arg::with_name("MODES_DIR")
+                    .short("m")
+                    .long("modes")
+                    .takes_value(true)
+                    .help(
+                        "Directory of game mode definitions to load instead of the built in modes",
+                    ),
+            )
+            .arg(
+                Arg::with_name("THINK_TIME")
+                    .long("think-time")
+                    .takes_value(true)
+                    .help("Milliseconds the expert computer player searches for each move (default 1000)"),
+            )
+            .arg(
+                Arg::with_name("SEED")
+                    .long("seed")
+                    .takes_value(true)
+                    .help("If specified, computer players place and move the same way every game"),
+            )
+            .arg(
+                Arg::with_name("MAX_ITERATIONS")
+                    .long("max-iterations")
+                    .takes_value(true)
+                    .help("If specified, the expert computer player stops searching after this many iterations"),
+            )
+            .arg(
+                Arg::with_name("IDLE_TIMEOUT")
+                    .long("idle-timeout")
+                    .takes_value(true)
+                    .help("Seconds without hearing from a client before it is disconnected (default 30)"),
+            )
+            .arg(
+                Arg::with_name("HEARTBEAT_INTERVAL")
+                    .long("heartbeat-interval")
+                    .takes_value(true)
+                    .help("Seconds between keep alives sent to every client (default 5)"),
+            )
+            .arg(
+                Arg::with_name("RECONNECT_GRACE")
+                    .long("reconnect-grace")
+                    .takes_value(true)
+                    .help("Seconds a player in a game has to resume after their connection drops (default 60)"),
+            )
+            .arg(
+                Arg::with_name("TCP_ADDR")
+                    .long("tcp-addr")
+                    .takes_value(true)
+                    .help("Address to listen for TCP clients on (default 0.0.0.0:32500)"),
+            )
+            .arg(
+                Arg::with_name("WS_ADDR")
+                    .long("ws-addr")
+                    .takes_value(true)
+                    .help("If specified, WebSocket clients are also accepted on this address"),
+            )
+            .arg(
+                Arg::with_name("TLS_ADDR")
+                    .long("tls-addr")
+                    .takes_value(true)
+                    .help("If specified, TLS clients are also accepted on this address"),
+            )
+            .arg(
+                Arg::with_name("TLS_CERT")
+                    .long("tls-cert")
+                    .takes_value(true)
+                    .help("PEM file with the certificate chain of the TLS listener"),
+            )
+            .arg(
+                Arg::with_name("TLS_KEY")
+                    .long("tls-key")
+                    .takes_value(true)
+                    .help("PEM file with the private key of the TLS listener"),
+            )
+            .get_matches();
+
+        let mut config = Self {
+            config_file: args
+                .value_of("CONFIG")
+                .map(|x| x.to_owned())
+                .or_else(|| env::var(CONFIG_ENV).ok()),
+            ..Self::default()
+        };
+        if let Some(path) = &config.config_file {
+            Overrides::from_file(path)?.apply(&mut config)?;
+        }
+        Overrides::from_env(|key| env::var(key).ok())?.apply(&mut config)?;
+
+        config.one_player |= args.is_present("ONE_PLAYER");
+        config.swift_game_enter |= args.is_present("SWIFT_GAME_ENTER");
+        config.ignore_turns |= args.is_present("IGNORE_TURNS");
+        config.log_packet_output |= args.is_present("LOG_PACKET_OUTPUT");
+        if let Some(dir) = args.value_of("MODES_DIR") {
+            config.modes_dir = Some(dir.to_owned());
+        }
+        if let Some(value) = args.value_of("THINK_TIME") {
+            config.think_time_ms = parse_value("--think-time", value)?;
+        }
+        if let Some(value) = args.value_of("SEED") {
+            config.seed = Some(parse_value("--seed", value)?);
+        }
+        if let Some(value) = args.value_of("MAX_ITERATIONS") {
+            config.max_iterations = Some(parse_value("--max-iterations", value)?);
+        }
+        if let Some(value) = args.value_of("IDLE_TIMEOUT") {
+            config.idle_timeout_secs = parse_value("--idle-timeout", value)?;
+        }
+        if let Some(value) = args.value_of("HEARTBEAT_INTERVAL") {
+            config.heartbeat_interval_secs = parse_value("--heartbeat-interval", value)?;
+        }
+        if let Some(value) = args.value_of("RECONNECT_GRACE") {
+            config.reconnect_grace_secs = parse_value("--reconnect-grace", value)?;
+        }
+        if let Some(addr) = args.value_of("TCP_ADDR") {
+            config.tcp_addr = addr.to_owned();
+        }
+        if let Some(addr) = args.value_of("WS_ADDR") {
+            config.ws_addr = Some(addr.to_owned());
+        }
+        if let Some(addr) = args.value_of("TLS_ADDR") {
+            config.tls_addr = Some(addr.to_owned());
+        }
+        if let Some(cert) = args.value_of("TLS_CERT") {
+            config.tls_cert = Some(cert.to_owned());
+        }
+        if let Some(key) = args.value_of("TLS_KEY") {
+            config.tls_key = Some(key.to_owned());
+        }
 
-        let one_player = args.is_present("ONE_PLAYER");
-        let swift_game_enter = args.is_present("SWIFT_GAME_ENTER");
-        let mut ignore_turns = args.is_present("IGNORE_TURNS");
-        let log_packet_output = args.is_present("LOG_PACKET_OUTPUT");
+        config.validate()?;
+        Ok(config)
+    }
 
-        if one_player {
-            ignore_turns = true;
+    /// Checks for values the server cannot run with
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let addrs = [
+            ("tcp_addr", Some(&self.tcp_addr)),
+            ("ws_addr", self.ws_addr.as_ref()),
+            ("tls_addr", self.tls_addr.as_ref()),
+        ];
+        for (name, addr) in addrs.iter() {
+            if let Some(addr) = addr {
+                addr.to_socket_addrs()
+                    .map_err(|e| ConfigError::new(name, format!("'{}': {}", addr, e)))?;
+            }
+        }
+        if self.tls_addr.is_some() && (self.tls_cert.is_none() || self.tls_key.is_none()) {
+            return Err(ConfigError::new(
+                "tls_addr",
+                "a certificate and key are required",
+            ));
         }
 
-        Self {
-            one_player,
-            swift_game_enter,
-            ignore_turns,
-            log_packet_output,
+        let positive = [
+            ("think_time_ms", self.think_time_ms),
+            ("max_iterations", self.max_iterations.map_or(1, u64::from)),
+            ("idle_timeout_secs", self.idle_timeout_secs),
+            ("heartbeat_interval_secs", self.heartbeat_interval_secs),
+            ("reconnect_grace_secs", self.reconnect_grace_secs),
+            ("max_rooms", self.max_rooms as u64),
+            ("prune_interval_secs", self.prune_interval_secs),
+            ("max_prune_age_secs", self.max_prune_age_secs),
+            ("max_username_length", self.max_username_length as u64),
+        ];
+        for (name, value) in positive.iter() {
+            if *value == 0 {
+                return Err(ConfigError::new(name, "must be greater than 0"));
+            }
         }
+        // Clients only send something by themselves when they have to
+        if self.heartbeat_interval_secs >= self.idle_timeout_secs {
+            return Err(ConfigError::new(
+                "heartbeat_interval_secs",
+                "must be shorter than idle_timeout_secs",
+            ));
+        }
+        Ok(())
     }
 
     pub fn log(&self) {
@@ -69,17 +343,27 @@ impl CliConfig {
         info!("| SWIFT_GAME_ENTER: {}", self.swift_game_enter);
         info!("| IGNORE_TURNS: {}", self.ignore_turns);
         info!("| LOG_PACKET_OUTPUT: {}", self.log_packet_output);
-    }
-}
-
-impl default::Default for CliConfig {
-    fn default() -> Self {
-        Self {
-            one_player: false,
-            swift_game_enter: false,
-            ignore_turns: false,
-            log_packet_output: false,
-        }
+        info!("| LOG_LEVEL: {}", self.log_level);
+        info!("| CONFIG_FILE: {:?}", self.config_file);
+        info!("| MODES_DIR: {:?}", self.modes_dir);
+        info!("| THINK_TIME_MS: {}", self.think_time_ms);
+        info!("| SEED: {:?}", self.seed);
+        info!("| MAX_ITERATIONS: {:?}", self.max_iterations);
+        info!("| IDLE_TIMEOUT_SECS: {}", self.idle_timeout_secs);
+        info!(
+            "| HEARTBEAT_INTERVAL_SECS: {}",
+            self.heartbeat_interval_secs
+        );
+        info!("| RECONNECT_GRACE_SECS: {}", self.reconnect_grace_secs);
+        info!("| TCP_ADDR: {}", self.tcp_addr);
+        info!("| WS_ADDR: {:?}", self.ws_addr);
+        info!("| TLS_ADDR: {:?}", self.tls_addr);
+        info!("| MAX_ROOMS: {}", self.max_rooms);
+        info!("| PRUNE_INTERVAL_SECS: {}", self.prune_interval_secs);
+        info!("| MAX_PRUNE_AGE_SECS: {}", self.max_prune_age_secs);
+        info!("| MAX_USERNAME_LENGTH: {}", self.max_username_length);
+        info!("| COUNTDOWN_SECS: {}", self.countdown_secs);
+        info!("| ATTACK_DELAY_SECS: {}", self.attack_delay_secs);
     }
 }
 
@@ -88,3 +372,97 @@ pub fn wait_for_command() -> Result<String, Box<dyn std::error::Error>> {
     std::io::stdin().read_line(&mut buffer)?;
     Ok(buffer.trim().to_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn file_then_env() {
+        let file = r#"
+            tcp_addr = "127.0.0.1:4000"
+            max_rooms = 20
+            countdown_secs = 3
+            reconnect_grace_secs = 90
+            seed = 42
+            log_level = "debug"
+            ws_addr = "127.0.0.1:4001"
+        "#;
+        let env: HashMap<&str, &str> = vec![
+            ("STRATEPIG_MAX_ROOMS", "50"),
+            ("STRATEPIG_ONE_PLAYER", "true"),
+            ("STRATEPIG_MAX_ITERATIONS", "500"),
+            ("STRATEPIG_HEARTBEAT_INTERVAL_SECS", "10"),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut config = CliConfig::default();
+        Overrides::parse("test.toml", file)
+            .unwrap()
+            .apply(&mut config)
+            .unwrap();
+        Overrides::from_env(|key| env.get(key).map(|x| x.to_string()))
+            .unwrap()
+            .apply(&mut config)
+            .unwrap();
+
+        assert_eq!(config.tcp_addr, "127.0.0.1:4000");
+        assert_eq!(config.ws_addr.as_deref(), Some("127.0.0.1:4001"));
+        assert_eq!(config.max_rooms, 50);
+        assert_eq!(config.countdown_secs, 3);
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(config.max_iterations, Some(500));
+        assert_eq!(config.reconnect_grace_secs, 90);
+        assert_eq!(config.heartbeat_interval_secs, 10);
+        assert_eq!(config.log_level, LevelFilter::Debug);
+        assert!(config.one_player);
+        assert_eq!(config.attack_delay_secs, DEFAULT_ATTACK_DELAY_SECS);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert!(Overrides::parse("test.toml", "max_room = 20").is_err());
+        assert!(Overrides::parse("test.toml", "max_rooms = \"many\"").is_err());
+        assert!(Overrides::from_env(|key| {
+            (key == "STRATEPIG_IDLE_TIMEOUT_SECS").then(|| "soon".to_owned())
+        })
+        .is_err());
+
+        let mut config = CliConfig::default();
+        let level = Overrides::parse("test.toml", "log_level = \"loud\"").unwrap();
+        assert!(level.apply(&mut config).is_err());
+
+        let config = CliConfig {
+            prune_interval_secs: 0,
+            ..CliConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = CliConfig {
+            heartbeat_interval_secs: DEFAULT_IDLE_TIMEOUT_SECS,
+            ..CliConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = CliConfig {
+            max_iterations: Some(0),
+            ..CliConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = CliConfig {
+            tls_addr: Some("0.0.0.0:32501".to_owned()),
+            ..CliConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = CliConfig {
+            tcp_addr: "32500".to_owned(),
+            ..CliConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use serde::Deserialize;
+use std::fmt::Display;
+use std::fs;
+use std::str::FromStr;
+
+use crate::{CliConfig, ConfigError};
+
+/// Prefix of the environment variables overriding the config, followed by the field name
+pub const ENV_PREFIX: &str = "STRATEPIG_";
+
+/// Values given by the config file or the environment, replacing those
+/// of the config where present
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Overrides {
+    one_player: Option<bool>,
+    swift_game_enter: Option<bool>,
+    ignore_turns: Option<bool>,
+    log_packet_output: Option<bool>,
+    log_level: Option<String>,
+    modes_dir: Option<String>,
+    think_time_ms: Option<u64>,
+    seed: Option<u64>,
+    max_iterations: Option<u32>,
+    idle_timeout_secs: Option<u64>,
+    heartbeat_interval_secs: Option<u64>,
+    reconnect_grace_secs: Option<u64>,
+    tcp_addr: Option<String>,
+    ws_addr: Option<String>,
+    tls_addr: Option<String>,
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+    max_rooms: Option<usize>,
+    prune_interval_secs: Option<u64>,
+    max_prune_age_secs: Option<u64>,
+    max_username_length: Option<usize>,
+    countdown_secs: Option<u64>,
+    attack_delay_secs: Option<u64>,
+}
+
+impl Overrides {
+    /// Reads a TOML config file, with each key named after a field of the config
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| ConfigError::new(path, e.to_string()))?;
+        Self::parse(path, &contents)
+    }
+
+    pub fn parse(file: &str, contents: &str) -> Result<Self, ConfigError> {
+        toml::from_str(contents).map_err(|e| ConfigError::new(file, e.to_string()))
+    }
+
+    /// Reads the variables named after the fields of the config in upper case,
+    /// such as `STRATEPIG_TCP_ADDR`, looking each one up with `var`
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        macro_rules! read {
+            ($($field:ident),*) => {
+                Self {
+                    $($field: env_value(&var, stringify!($field))?,)*
+                }
+            };
+        }
+
+        Ok(read!(
+            one_player,
+            swift_game_enter,
+            ignore_turns,
+            log_packet_output,
+            log_level,
+            modes_dir,
+            think_time_ms,
+            seed,
+            max_iterations,
+            idle_timeout_secs,
+            heartbeat_interval_secs,
+            reconnect_grace_secs,
+            tcp_addr,
+            ws_addr,
+            tls_addr,
+            tls_cert,
+            tls_key,
+            max_rooms,
+            prune_interval_secs,
+            max_prune_age_secs,
+            max_username_length,
+            countdown_secs,
+            attack_delay_secs
+        ))
+    }
+
+    pub fn apply(self, config: &mut CliConfig) -> Result<(), ConfigError> {
+        if let Some(level) = &self.log_level {
+            config.log_level = parse_value("log_level", level)?;
+        }
+
+        macro_rules! set {
+            ($($field:ident),*) => {
+                $(if let Some(value) = self.$field {
+                    config.$field = value;
+                })*
+            };
+        }
+        // Fields that are optional in the config as well
+        macro_rules! set_some {
+            ($($field:ident),*) => {
+                $(if self.$field.is_some() {
+                    config.$field = self.$field;
+                })*
+            };
+        }
+
+        set!(
+            one_player,
+            swift_game_enter,
+            ignore_turns,
+            log_packet_output,
+            think_time_ms,
+            idle_timeout_secs,
+            heartbeat_interval_secs,
+            reconnect_grace_secs,
+            tcp_addr,
+            max_rooms,
+            prune_interval_secs,
+            max_prune_age_secs,
+            max_username_length,
+            countdown_secs,
+            attack_delay_secs
+        );
+        set_some!(
+            modes_dir,
+            seed,
+            max_iterations,
+            ws_addr,
+            tls_addr,
+            tls_cert,
+            tls_key
+        );
+        Ok(())
+    }
+}
+
+fn env_value<T: FromStr>(
+    var: &impl Fn(&str) -> Option<String>,
+    field: &str,
+) -> Result<Option<T>, ConfigError>
+where
+    T::Err: Display,
+{
+    let key = format!("{}{}", ENV_PREFIX, field.to_uppercase());
+    match var(&key) {
+        Some(value) => parse_value(&key, &value).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e| ConfigError::new(name, format!("'{}': {}", value, e)))
+}
@@ -1,10 +1,14 @@
 #[derive(Debug)]
+// The hidden variant keeps matches outside the crate non-exhaustive
+#[allow(clippy::manual_non_exhaustive)]
 pub enum Error {
     Io(std::io::Error),
 
     FailedToSendBytes,
     FailedToRegisterForEvents,
     InvalidData(String),
+    /// A packet header claims a body larger than `MAX_PACKET_BODY_SIZE`
+    PacketTooLarge(usize),
     ConnectionNotFound,
 
     #[doc(hidden)]
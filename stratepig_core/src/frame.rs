@@ -0,0 +1,149 @@
+use crate::error::Error;
+use crate::packet::{deserialize_packet_header, Packet, PACKET_HEADER_SIZE};
+
+/// Splits a stream of bytes into packets.
+/// Bytes of a packet that has not fully arrived are kept until the next read.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds bytes read from the stream and returns every packet they complete.
+    /// A header with a bad length fails the whole stream, as there is no way
+    /// to find where the next packet starts.
+    pub fn decode(&mut self, data: &[u8]) -> Result<Vec<Packet>, Error> {
+        self.buffer.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        let mut start = 0;
+        while self.buffer.len() - start >= PACKET_HEADER_SIZE {
+            let header = match deserialize_packet_header(&self.buffer[start..]) {
+                Ok(header) => header,
+                Err(err) => {
+                    self.buffer.clear();
+                    return Err(err);
+                }
+            };
+
+            let body_start = start + PACKET_HEADER_SIZE;
+            let end = body_start + header.size as usize;
+            if self.buffer.len() < end {
+                break;
+            }
+
+            let body = self.buffer[body_start..end].to_vec();
+            packets.push(Packet { header, body });
+            start = end;
+        }
+
+        self.buffer.drain(..start);
+        Ok(packets)
+    }
+
+    /// Bytes waiting for the rest of their packet
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_PACKET_BODY_SIZE;
+
+    fn frame(id: u8, body: &[u8]) -> Vec<u8> {
+        let mut data = (body.len() as u16).to_le_bytes().to_vec();
+        data.push(id);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn stream() -> Vec<u8> {
+        let mut data = frame(1, b"welcome");
+        data.extend(frame(0, &[]));
+        data.extend(frame(8, &[4, 2, 0, 9, 9]));
+        data
+    }
+
+    fn summary(packets: &[Packet]) -> Vec<(u8, Vec<u8>)> {
+        packets
+            .iter()
+            .map(|x| (x.header.id, x.body.clone()))
+            .collect()
+    }
+
+    fn expected() -> Vec<(u8, Vec<u8>)> {
+        vec![
+            (1, b"welcome".to_vec()),
+            (0, Vec::new()),
+            (8, vec![4, 2, 0, 9, 9]),
+        ]
+    }
+
+    #[test]
+    fn decodes_coalesced_packets() {
+        let mut decoder = FrameDecoder::new();
+        let packets = decoder.decode(&stream()).unwrap();
+        assert_eq!(summary(&packets), expected());
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn decodes_split_at_every_offset() {
+        let data = stream();
+        for split in 0..=data.len() {
+            let mut decoder = FrameDecoder::new();
+            let mut packets = decoder.decode(&data[..split]).unwrap();
+            packets.extend(decoder.decode(&data[split..]).unwrap());
+            assert_eq!(summary(&packets), expected(), "split at {}", split);
+            assert_eq!(decoder.buffered(), 0);
+        }
+
+        for first in 0..=data.len() {
+            for second in first..=data.len() {
+                let mut decoder = FrameDecoder::new();
+                let mut packets = decoder.decode(&data[..first]).unwrap();
+                packets.extend(decoder.decode(&data[first..second]).unwrap());
+                packets.extend(decoder.decode(&data[second..]).unwrap());
+                assert_eq!(summary(&packets), expected());
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_byte_by_byte() {
+        let mut decoder = FrameDecoder::new();
+        let mut packets = Vec::new();
+        for byte in stream() {
+            packets.extend(decoder.decode(&[byte]).unwrap());
+        }
+        assert_eq!(summary(&packets), expected());
+    }
+
+    #[test]
+    fn keeps_partial_packets() {
+        let mut decoder = FrameDecoder::new();
+        let data = frame(3, b"partial");
+        assert!(decoder.decode(&data[..5]).unwrap().is_empty());
+        assert_eq!(decoder.buffered(), 5);
+    }
+
+    #[test]
+    fn rejects_bad_lengths() {
+        let mut decoder = FrameDecoder::new();
+        let mut data = frame(1, b"ok");
+        data.extend((MAX_PACKET_BODY_SIZE as u16).to_le_bytes());
+        data.push(2);
+
+        match decoder.decode(&data) {
+            Err(Error::PacketTooLarge(size)) => assert_eq!(size, MAX_PACKET_BODY_SIZE),
+            other => panic!("expected a length error, got {:?}", other.map(|x| x.len())),
+        }
+        assert_eq!(decoder.buffered(), 0);
+    }
+}
@@ -2,19 +2,32 @@
 //! The core module for Stratepig containing server, client, packet data and more.
 //! ### Modules
 //! - Packet (contains packet implementation for writing and reading data as bytes)
-//! - Server (contains function to start the server and listen at a port)
+//! - Frame (splits a stream of bytes into packets)
+//! - Registry (lists every packet type defined with the packet macros)
 //!
 //! ### Example
-//! #### How to start a server
+//! #### How to read packets from a stream
 //! ```
-//! use stratepig_core::server;
+//! use stratepig_core::FrameDecoder;
 //!
-//! fn main() {
-//!     server::start();
-//! }
+//! let mut decoder = FrameDecoder::new();
+//! // A packet with id 13 and a two byte body, arriving over two reads
+//! assert!(decoder.decode(&[2, 0, 13]).unwrap().is_empty());
+//! let packets = decoder.decode(&[7, 0]).unwrap();
+//!
+//! assert_eq!(packets[0].header.id, 13);
+//! assert_eq!(packets[0].body, vec![7, 0]);
 //! ```
 mod error;
+mod frame;
 mod packet;
+mod registry;
 
 pub use error::Error;
+pub use frame::FrameDecoder;
 pub use packet::*;
+pub use registry::*;
+
+// Used by the packet macros
+#[doc(hidden)]
+pub use inventory;
@@ -53,7 +53,7 @@ pub fn deserialize_packet_header(data: &[u8]) -> Result<PacketHeader, Error> {
     let body_size = reader.read_u16::<LittleEndian>()? as usize;
 
     if body_size >= MAX_PACKET_BODY_SIZE {
-        return Err(Error::InvalidData("packet body too large".to_owned()));
+        return Err(Error::PacketTooLarge(body_size));
     }
 
     let packet_id = reader.read_u8()?;
@@ -0,0 +1,30 @@
+/// Which side of the connection sends a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Server,
+    Client,
+}
+
+/// A packet type, registered by the `server_packet` and `client_packet` macros
+#[derive(Debug)]
+pub struct PacketInfo {
+    pub id: u8,
+    pub name: &'static str,
+    pub direction: Direction,
+    /// Whether this is another layout of a packet sharing its id
+    pub variant: bool,
+}
+
+inventory::collect!(PacketInfo);
+
+/// Every packet type in the program
+pub fn registered_packets() -> impl Iterator<Item = &'static PacketInfo> {
+    inventory::iter::<PacketInfo>.into_iter()
+}
+
+/// Name of the packet sent in `direction` with `id`, ignoring variants
+pub fn packet_name(direction: Direction, id: u8) -> Option<&'static str> {
+    registered_packets()
+        .find(|x| x.direction == direction && x.id == id && !x.variant)
+        .map(|x| x.name)
+}
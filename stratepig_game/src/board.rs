@@ -2,78 +2,129 @@ use crate::Piece;
 
 pub type Board = Vec<Piece>;
 
-pub const BOTTOM_LEFT_TILE: u8 = 1;
-pub const TOP_RIGHT_TILE: u8 = 100;
-pub const STARTING_TERRITORY_VALUE: u8 = 40;
-pub const WATER_TILES: [u8; 8] = [43, 44, 47, 48, 53, 54, 57, 58];
+/// Shape of the board, with tiles numbered from 1 in the bottom left corner,
+/// going left to right and then bottom to top.
+/// The total number of tiles must fit within a `u8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardLayout {
+    pub width: u8,
+    pub height: u8,
+    /// Water tiles that no pig can enter or move through
+    pub lakes: Vec<u8>,
+    /// Rows from the bottom of the board each side places its pigs in
+    pub setup_rows: u8,
+}
+
+impl BoardLayout {
+    /// The original 10x10 board with two lakes in the middle
+    pub fn standard() -> Self {
+        Self {
+            width: 10,
+            height: 10,
+            lakes: vec![43, 44, 47, 48, 53, 54, 57, 58],
+            setup_rows: 4,
+        }
+    }
+
+    /// A smaller 8x8 board for quick games with four single tile lakes
+    pub fn lightning() -> Self {
+        Self {
+            width: 8,
+            height: 8,
+            lakes: vec![27, 30, 35, 38],
+            setup_rows: 3,
+        }
+    }
+
+    pub fn tile_count(&self) -> u8 {
+        self.width * self.height
+    }
+
+    /// Number of tiles each side can place pigs on
+    pub fn setup_tiles(&self) -> u8 {
+        self.width * self.setup_rows
+    }
+
+    pub fn is_lake(&self, pos: u8) -> bool {
+        self.lakes.contains(&pos)
+    }
+}
+
+impl Default for BoardLayout {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
 
-pub fn in_bounds(pos: i16) -> bool {
-    pos >= BOTTOM_LEFT_TILE as i16 && pos <= TOP_RIGHT_TILE as i16
+pub fn in_bounds(layout: &BoardLayout, pos: i16) -> bool {
+    pos >= 1 && pos <= layout.tile_count() as i16
 }
 
-pub fn in_starting_bounds(pos: i16) -> bool {
-    pos >= BOTTOM_LEFT_TILE as i16 && pos <= STARTING_TERRITORY_VALUE as i16
+pub fn in_starting_bounds(layout: &BoardLayout, pos: i16) -> bool {
+    pos >= 1 && pos <= layout.setup_tiles() as i16
 }
 
 #[allow(dead_code)]
-pub fn get_adjacent(pos: u8) -> Vec<u8> {
+pub fn get_adjacent(layout: &BoardLayout, pos: u8) -> Vec<u8> {
+    let width = layout.width as i16;
     let pos = pos as i16;
-    let initial = vec![pos + 1, pos - 1, pos + 10, pos - 10];
+    let initial = vec![pos + 1, pos - 1, pos + width, pos - width];
     let mut result: Vec<u8> = initial
         .into_iter()
-        .filter(|x| in_bounds(*x) && !WATER_TILES.contains(&(*x as u8)))
+        .filter(|x| in_bounds(layout, *x) && !layout.is_lake(*x as u8))
         .map(|x| x as u8)
         .collect();
 
-    if pos % 10 == 0 {
-        result = result.into_iter().filter(|x| *x != pos as u8 + 1).collect();
+    if pos % width == 0 {
+        result.retain(|x| *x != pos as u8 + 1);
     }
-    if pos - 1 == 0 || (pos - 1) % 10 == 0 {
-        result = result.into_iter().filter(|x| *x != pos as u8 - 1).collect();
+    if (pos - 1) % width == 0 {
+        result.retain(|x| *x != pos as u8 - 1);
     }
 
     result
 }
 
-pub fn get_column(pos: u8) -> u8 {
-    let pos = pos % 10;
+pub fn get_column(layout: &BoardLayout, pos: u8) -> u8 {
+    let pos = pos % layout.width;
     if pos != 0 {
-        return pos;
+        pos
     } else {
-        return 10;
+        layout.width
     }
 }
 
 #[allow(dead_code)]
-pub fn get_scout(pos: u8) -> Vec<u8> {
-    let row = (pos - 1) / 10;
-    let column = get_column(pos);
+pub fn get_scout(layout: &BoardLayout, pos: u8) -> Vec<u8> {
+    let width = layout.width;
+    let row = (pos - 1) / width;
+    let column = get_column(layout, pos);
     let mut result = Vec::new();
 
-    for x in column..11 {
-        let val = row * 10 + x;
-        if WATER_TILES.contains(&val) {
+    for x in column..width + 1 {
+        let val = row * width + x;
+        if layout.is_lake(val) {
             break;
         }
         result.push(val);
     }
     for x in (1..column).rev() {
-        let val = row * 10 + x;
-        if WATER_TILES.contains(&val) {
+        let val = row * width + x;
+        if layout.is_lake(val) {
             break;
         }
         result.push(val);
     }
-    for y in row + 1..10 {
-        let val = y * 10 + column;
-        if WATER_TILES.contains(&val) {
+    for y in row + 1..layout.height {
+        let val = y * width + column;
+        if layout.is_lake(val) {
             break;
         }
         result.push(val);
     }
     for y in (0..row).rev() {
-        let val = y * 10 + column;
-        if WATER_TILES.contains(&val) {
+        let val = y * width + column;
+        if layout.is_lake(val) {
             break;
         }
         result.push(val);
@@ -82,14 +133,17 @@ pub fn get_scout(pos: u8) -> Vec<u8> {
     result.into_iter().filter(|x| *x != pos).collect()
 }
 
-pub fn flip_tile(pos: u8) -> u8 {
-    100 - pos + 1
+pub fn flip_tile(layout: &BoardLayout, pos: u8) -> u8 {
+    layout.tile_count() - pos + 1
 }
 
-pub fn flip_board(board: &Board) -> Board {
+pub fn flip_board(layout: &BoardLayout, board: &Board) -> Board {
     let mut result = Board::new();
     for piece in board.iter() {
-        result.push(Piece::new(piece.pig, flip_tile(piece.location)))
+        result.push(Piece {
+            location: flip_tile(layout, piece.location),
+            ..piece.clone()
+        })
     }
     result
 }
@@ -101,10 +155,11 @@ pub fn sum_boards(local: &Board, opp: &Board) -> Board {
     board
 }
 
-pub fn pig_in_path(total_board: &Board, from: u8, to: u8) -> bool {
+pub fn pig_in_path(layout: &BoardLayout, total_board: &Board, from: u8, to: u8) -> bool {
+    let width = layout.width;
     let right_or_up = to > from;
-    let row_from = (from - 1) / 10;
-    let row_to = (to - 1) / 10;
+    let row_from = (from - 1) / width;
+    let row_to = (to - 1) / width;
 
     macro_rules! check {
         ($loc:expr) => {
@@ -120,7 +175,7 @@ pub fn pig_in_path(total_board: &Board, from: u8, to: u8) -> bool {
                 check!(i);
             }
         } else {
-            for i in (from + 10..to).step_by(10) {
+            for i in (from + width..to).step_by(width as usize) {
                 check!(i);
             }
         }
@@ -130,7 +185,7 @@ pub fn pig_in_path(total_board: &Board, from: u8, to: u8) -> bool {
                 check!(i);
             }
         } else {
-            for i in (to + 10..from).step_by(10) {
+            for i in (to + width..from).step_by(width as usize) {
                 check!(i);
             }
         }
@@ -156,12 +211,13 @@ mod tests {
             vec![61, 62, 64, 65, 66, 67, 68, 69, 70, 73, 83, 93],
         ];
 
+        let layout = BoardLayout::standard();
         for (index, test) in tests.into_iter().enumerate() {
-            let result = get_scout(test);
+            let result = get_scout(&layout, test);
             println!("---------------------------");
             println!("--- Testing Scout at {} ---", test);
             println!("---------------------------");
-            test_util::print_path(test, result.clone());
+            test_util::print_path(&layout, test, result.clone());
 
             assert!(
                 result.len() == solutions[index].len()
@@ -183,12 +239,13 @@ mod tests {
             vec![90, 99],
         ];
 
+        let layout = BoardLayout::standard();
         for (index, test) in tests.into_iter().enumerate() {
-            let result = get_adjacent(test);
+            let result = get_adjacent(&layout, test);
             println!("---------------------------");
             println!("--- Testing Regular at {} ---", test);
             println!("---------------------------");
-            test_util::print_path(test, result.clone());
+            test_util::print_path(&layout, test, result.clone());
 
             assert!(
                 result.len() == solutions[index].len()
@@ -196,4 +253,46 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn check_lightning_paths() {
+        let layout = BoardLayout::lightning();
+
+        let mut result = get_adjacent(&layout, 8);
+        result.sort_unstable();
+        assert_eq!(result, vec![7, 16]);
+
+        // Lake at 27 blocks the way up from 19 and right from 26
+        let mut result = get_adjacent(&layout, 19);
+        result.sort_unstable();
+        assert_eq!(result, vec![11, 18, 20]);
+
+        let mut result = get_scout(&layout, 26);
+        test_util::print_path(&layout, 26, result.clone());
+        result.sort_unstable();
+        assert_eq!(result, vec![2, 10, 18, 25, 34, 42, 50, 58]);
+
+        let mut result = get_scout(&layout, 64);
+        result.sort_unstable();
+        assert_eq!(
+            result,
+            vec![8, 16, 24, 32, 40, 48, 56, 57, 58, 59, 60, 61, 62, 63]
+        );
+    }
+
+    #[test]
+    fn check_flip() {
+        for layout in [BoardLayout::standard(), BoardLayout::lightning()].iter() {
+            assert_eq!(flip_tile(layout, 1), layout.tile_count());
+            // Lakes must look the same from both sides
+            for lake in layout.lakes.iter() {
+                assert!(layout.is_lake(flip_tile(layout, *lake)));
+            }
+        }
+
+        let layout = BoardLayout::lightning();
+        assert!(in_starting_bounds(&layout, 24));
+        assert!(!in_starting_bounds(&layout, 25));
+        assert!(!in_bounds(&layout, 65));
+    }
 }
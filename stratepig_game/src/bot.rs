@@ -0,0 +1,240 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::time::Duration;
+
+use crate::board::flip_tile;
+use crate::interaction::{resolve_attack_with, InteractionResult};
+use crate::ismcts::{search, SearchConfig};
+use crate::movegen::legal_moves;
+use crate::pig::Pig;
+use crate::role::PlayerRole;
+use crate::state::{GameState, Move};
+
+/// How a computer player picks its moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    /// Any legal move
+    Random = 1,
+    /// The move with the best expected trade, only using what the opponent could know
+    Greedy = 2,
+    /// An information set search, see `search`
+    Expert = 3,
+}
+
+impl Difficulty {
+    pub const MAX: u32 = 3;
+
+    /// Converts a settings value, where 0 means there is no computer player
+    pub fn from(val: u32) -> Option<Self> {
+        match val {
+            1 => Some(Self::Random),
+            2 => Some(Self::Greedy),
+            3 => Some(Self::Expert),
+            _ => None,
+        }
+    }
+}
+
+/// Picks the next move for `role`, or `None` if it has no legal moves.
+/// Expert players search for the default iterations of `SearchConfig` without a time
+/// budget, so the same generator always picks the same move.
+pub fn choose_move<R: Rng>(
+    state: &GameState,
+    role: PlayerRole,
+    difficulty: Difficulty,
+    rng: &mut R,
+) -> Option<Move> {
+    let moves = legal_moves(state, role);
+    match difficulty {
+        Difficulty::Random => moves.choose(rng).copied(),
+        Difficulty::Greedy => {
+            let scored: Vec<(Move, f32)> = moves
+                .into_iter()
+                .map(|mv| (mv, score_move(state, role, mv)))
+                .collect();
+            let best = scored.iter().map(|x| x.1).fold(f32::NEG_INFINITY, f32::max);
+            let best: Vec<Move> = scored
+                .into_iter()
+                .filter(|x| x.1 >= best)
+                .map(|x| x.0)
+                .collect();
+            best.choose(rng).copied()
+        }
+        Difficulty::Expert => {
+            let config = SearchConfig {
+                time_budget: Duration::MAX,
+                ..SearchConfig::default()
+            };
+            search(state, role, &config, rng)
+        }
+    }
+}
+
+/// Rough worth of a pig when trading it away
+pub(crate) fn value(pig: Pig) -> f32 {
+    match pig {
+        Pig::Flag => 100.0,
+        Pig::Bomb => 4.0,
+        Pig::Spy => 3.0,
+        pig => pig.rank() as f32,
+    }
+}
+
+/// Scores a move by the expected trade it makes. Enemy pigs are hidden,
+/// so attacks are weighed against every pig the opponent has left that
+/// could be on the target tile, where pigs that moved cannot be immovable.
+/// Revealed pigs are weighed as themselves.
+fn score_move(state: &GameState, role: PlayerRole, mv: Move) -> f32 {
+    let layout = &state.layout;
+    let enemy_board = state.board(role.opp());
+    let pig = match state.board(role).iter().find(|x| x.location == mv.from) {
+        Some(piece) => piece.pig,
+        None => return f32::NEG_INFINITY,
+    };
+
+    let target = match enemy_board
+        .iter()
+        .find(|x| x.location == flip_tile(layout, mv.to))
+    {
+        Some(target) => target,
+        None => {
+            // Slightly prefer moving towards the opponent
+            let row = |pos: u8| (pos - 1) / layout.width;
+            return if row(mv.to) > row(mv.from) { 0.1 } else { 0.0 };
+        }
+    };
+
+    let candidates: Vec<Pig> = if target.revealed {
+        vec![target.pig]
+    } else {
+        // Revealed pigs are known to be elsewhere
+        enemy_board
+            .iter()
+            .filter(|x| !x.revealed)
+            .map(|x| x.pig)
+            .filter(|x| !(target.moved && x.immovable()))
+            .collect()
+    };
+
+    let total = candidates.len() as f32;
+    candidates
+        .into_iter()
+        .map(|target| {
+            let (result, _ability) = resolve_attack_with(pig, target, &state.rules);
+            match result {
+                InteractionResult::Win => value(target),
+                InteractionResult::Lose => -value(pig),
+                InteractionResult::Tie => value(target) - value(pig),
+            }
+        })
+        .sum::<f32>()
+        / total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::{generate_setup, SetupStrategy};
+    use crate::{Board, BoardLayout, Piece};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    fn board(pieces: &[(Pig, u8)]) -> Board {
+        pieces
+            .iter()
+            .map(|(pig, loc)| Piece::new(*pig, *loc))
+            .collect()
+    }
+
+    fn config(pigs: &[(Pig, u8)]) -> HashMap<Pig, u8> {
+        pigs.iter().copied().collect()
+    }
+
+    #[test]
+    fn bots_play_legal_games() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let pig_config = config(&[
+            (Pig::Flag, 1),
+            (Pig::Bomb, 3),
+            (Pig::Scout, 4),
+            (Pig::Miner, 3),
+            (Pig::Kingo, 1),
+        ]);
+        let layout = BoardLayout::lightning();
+
+        for difficulty in [Difficulty::Random, Difficulty::Greedy].iter() {
+            let one = generate_setup(&layout, &pig_config, SetupStrategy::Random, &mut rng);
+            let two = generate_setup(&layout, &pig_config, SetupStrategy::Fortress, &mut rng);
+            let mut state = GameState::new(
+                one.unwrap(),
+                two.unwrap(),
+                PlayerRole::One,
+                pig_config.clone(),
+            );
+            state.layout = layout.clone();
+
+            for _ in 0..200 {
+                let role = state.current_turn;
+                let mv = match choose_move(&state, role, *difficulty, &mut rng) {
+                    Some(mv) => mv,
+                    None => break,
+                };
+                let outcome = state.apply_move(role, mv).unwrap();
+                if outcome.win.is_some() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_weighs_trades() {
+        let mut rng = StdRng::seed_from_u64(3);
+        // The enemy pig at 22 in the perspective of player one moved there,
+        // so it must be the general rather than the flag
+        let mut two = board(&[(Pig::Flag, 91), (Pig::General, 79)]);
+        two[1].moved = true;
+        let pieces = |pig| board(&[(Pig::Flag, 1), (pig, 12)]);
+
+        let state = GameState::new(
+            pieces(Pig::Kingo),
+            two.clone(),
+            PlayerRole::One,
+            config(&[]),
+        );
+        assert_eq!(
+            choose_move(&state, PlayerRole::One, Difficulty::Greedy, &mut rng),
+            Some(Move::new(12, 22))
+        );
+
+        let state = GameState::new(pieces(Pig::Miner), two, PlayerRole::One, config(&[]));
+        let mv = choose_move(&state, PlayerRole::One, Difficulty::Greedy, &mut rng).unwrap();
+        assert_ne!(mv.to, 22);
+
+        // A general that never moved could still be the flag, unless it was revealed
+        let mut two = board(&[(Pig::Flag, 91), (Pig::General, 79)]);
+        let state = GameState::new(
+            pieces(Pig::Miner),
+            two.clone(),
+            PlayerRole::One,
+            config(&[]),
+        );
+        assert_eq!(
+            choose_move(&state, PlayerRole::One, Difficulty::Greedy, &mut rng),
+            Some(Move::new(12, 22))
+        );
+
+        two[1].revealed = true;
+        let state = GameState::new(pieces(Pig::Miner), two, PlayerRole::One, config(&[]));
+        let mv = choose_move(&state, PlayerRole::One, Difficulty::Greedy, &mut rng).unwrap();
+        assert_ne!(mv.to, 22);
+
+        // Neither can it be a flag that was revealed elsewhere
+        let mut two = board(&[(Pig::Flag, 91), (Pig::General, 79)]);
+        two[0].revealed = true;
+        let state = GameState::new(pieces(Pig::Miner), two, PlayerRole::One, config(&[]));
+        let mv = choose_move(&state, PlayerRole::One, Difficulty::Greedy, &mut rng).unwrap();
+        assert_ne!(mv.to, 22);
+    }
+}
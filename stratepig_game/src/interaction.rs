@@ -1,3 +1,7 @@
+use crate::pig::{Ability, Pig};
+use crate::rules::RuleSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InteractionResult {
     Win = 1,
     Lose = 0,
@@ -13,3 +17,36 @@ impl InteractionResult {
         }
     }
 }
+
+/// Resolves an infiltration from the point of view of the initiator
+pub fn resolve_infiltration(initiator: Pig, target: Pig, guess: Pig) -> Option<InteractionResult> {
+    initiator.get_behavior().infiltrate(target, guess)
+}
+
+/// Resolves an attack from the point of view of the initiator
+pub fn resolve_attack(initiator: Pig, target: Pig) -> InteractionResult {
+    resolve_attack_with(initiator, target, &RuleSet::default()).0
+}
+
+/// Resolves an attack under the house rules of a room,
+/// also returning the ability that decided the result, if any
+pub fn resolve_attack_with(
+    initiator: Pig,
+    target: Pig,
+    rules: &RuleSet,
+) -> (InteractionResult, Option<Ability>) {
+    let behavior = initiator.behavior(rules);
+    let result = match target.behavior(rules).defense_override(initiator) {
+        // Target winning = current losing... inverse required
+        Some(result) => result.invert(),
+        None => match behavior.attack(initiator, target) {
+            InteractionResult::Tie if rules.attacker_wins_ties => InteractionResult::Win,
+            result => result,
+        },
+    };
+
+    match behavior.ability(target, result) {
+        Some(ability) => (InteractionResult::Win, Some(ability)),
+        None => (result, None),
+    }
+}
@@ -0,0 +1,428 @@
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+use crate::bot::value;
+use crate::movegen::legal_moves;
+use crate::pig::Pig;
+use crate::repetition::RepetitionRule;
+use crate::role::PlayerRole;
+use crate::state::{GameState, Move, Win};
+
+/// Iterations a search stops at unless told otherwise, so the same generator
+/// always leads to the same move when the time budget allows for them
+pub const DEFAULT_MAX_ITERATIONS: u32 = 2_000;
+
+/// Limits and tuning of an information set search
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchConfig {
+    /// Time to search for, checked between iterations
+    pub time_budget: Duration,
+    /// Stops the search early after this many iterations.
+    /// A search is only reproducible when this is reached before the time budget.
+    pub max_iterations: Option<u32>,
+    /// Random moves played from a new node before the position is evaluated
+    pub rollout_depth: u32,
+    /// Weight of rarely tried moves against moves that did well so far
+    pub exploration: f32,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            time_budget: Duration::from_secs(1),
+            max_iterations: Some(DEFAULT_MAX_ITERATIONS),
+            rollout_depth: 30,
+            exploration: 0.7,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    /// The move leading to this node, made by `player`
+    mv: Option<Move>,
+    player: PlayerRole,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    visits: u32,
+    /// Times this node could have been picked from its parent
+    availability: u32,
+    /// Total reward for `player`
+    reward: f32,
+}
+
+impl Node {
+    fn new(mv: Option<Move>, player: PlayerRole, parent: Option<usize>) -> Self {
+        Self {
+            mv,
+            player,
+            parent,
+            children: Vec::new(),
+            visits: 0,
+            availability: 1,
+            reward: 0.0,
+        }
+    }
+
+    fn ucb(&self, exploration: f32) -> f32 {
+        let visits = self.visits as f32;
+        self.reward / visits + exploration * ((self.availability as f32).ln() / visits).sqrt()
+    }
+}
+
+/// Picks a move for `role` with a single observer information set Monte Carlo tree search.
+/// Every iteration guesses the hidden enemy pigs with `determinize` and searches the
+/// shared tree using only the moves that are legal in that guess.
+pub fn search<R: Rng>(
+    state: &GameState,
+    role: PlayerRole,
+    config: &SearchConfig,
+    rng: &mut R,
+) -> Option<Move> {
+    let moves = legal_moves(state, role);
+    if moves.len() <= 1 {
+        return moves.first().copied();
+    }
+
+    let start = Instant::now();
+    let mut tree = vec![Node::new(None, role.opp(), None)];
+    let mut iterations = 0;
+
+    while config.max_iterations.is_none_or(|max| iterations < max)
+        && start.elapsed() < config.time_budget
+    {
+        let mut state = determinize(state, role, rng);
+        state.current_turn = role;
+        let mut node = 0;
+        let mut win = None;
+
+        // Selection and expansion
+        loop {
+            let player = state.current_turn;
+            let legal = legal_moves(&state, player);
+            if legal.is_empty() {
+                break;
+            }
+
+            let untried = legal.iter().filter(|mv| {
+                !tree[node]
+                    .children
+                    .iter()
+                    .any(|x| tree[*x].mv == Some(**mv))
+            });
+            if let Some(mv) = untried.choose(rng).copied() {
+                win = state.apply_move(player, mv).unwrap().win;
+                let child = tree.len();
+                tree.push(Node::new(Some(mv), player, Some(node)));
+                tree[node].children.push(child);
+                node = child;
+                break;
+            }
+
+            let available: Vec<usize> = tree[node]
+                .children
+                .iter()
+                .copied()
+                .filter(|x| legal.contains(&tree[*x].mv.unwrap()))
+                .collect();
+            for child in available.iter() {
+                tree[*child].availability += 1;
+            }
+            node = *available
+                .iter()
+                .max_by(|a, b| {
+                    let a = tree[**a].ucb(config.exploration);
+                    let b = tree[**b].ucb(config.exploration);
+                    a.total_cmp(&b)
+                })
+                .unwrap();
+
+            win = state
+                .apply_move(player, tree[node].mv.unwrap())
+                .unwrap()
+                .win;
+            if win.is_some() {
+                break;
+            }
+        }
+
+        let reward = match win {
+            Some(win) => win_reward(win, role),
+            None => rollout(&mut state, role, config.rollout_depth, rng),
+        };
+
+        let mut current = Some(node);
+        while let Some(i) = current {
+            tree[i].visits += 1;
+            tree[i].reward += if tree[i].player == role {
+                reward
+            } else {
+                1.0 - reward
+            };
+            current = tree[i].parent;
+        }
+        iterations += 1;
+    }
+
+    // Guessed pigs can make moves look legal that are not, such as with position repetition
+    tree[0]
+        .children
+        .iter()
+        .filter(|x| moves.contains(&tree[**x].mv.unwrap()))
+        .max_by_key(|x| tree[**x].visits)
+        .and_then(|x| tree[*x].mv)
+        .or_else(|| moves.choose(rng).copied())
+}
+
+/// Guesses the hidden pigs of the opponent of `role`. Hidden pigs are whatever the
+/// pig config leaves after the captured and revealed pigs, which are shuffled between
+/// the unrevealed pieces, keeping bombs and flags on pieces that never moved.
+pub(crate) fn determinize<R: Rng>(state: &GameState, role: PlayerRole, rng: &mut R) -> GameState {
+    let mut state = state.clone();
+    let board = state.board_mut(role.opp());
+
+    let hidden: Vec<usize> = (0..board.len()).filter(|i| !board[*i].revealed).collect();
+    let (immovable, mut movable): (Vec<Pig>, Vec<Pig>) = hidden
+        .iter()
+        .map(|i| board[*i].pig)
+        .partition(|x| x.immovable());
+
+    let unmoved: Vec<usize> = hidden
+        .iter()
+        .copied()
+        .filter(|i| !board[*i].moved)
+        .collect();
+    let stationary: Vec<usize> = unmoved
+        .choose_multiple(rng, immovable.len())
+        .copied()
+        .collect();
+    for (i, pig) in stationary.iter().zip(immovable) {
+        board[*i].pig = pig;
+    }
+
+    movable.shuffle(rng);
+    let rest = hidden.iter().filter(|x| !stationary.contains(x));
+    for (i, pig) in rest.zip(movable) {
+        board[*i].pig = pig;
+    }
+
+    state
+}
+
+/// Plays random moves and evaluates the position reached for `role`.
+/// Repetition is not checked during rollouts, as it only slows them down.
+fn rollout<R: Rng>(state: &mut GameState, role: PlayerRole, depth: u32, rng: &mut R) -> f32 {
+    state.rules.repetition = RepetitionRule::Off;
+
+    for _ in 0..depth {
+        let player = state.current_turn;
+        let mv = match legal_moves(state, player).choose(rng) {
+            Some(mv) => *mv,
+            None => break,
+        };
+        if let Some(win) = state.apply_move(player, mv).unwrap().win {
+            return win_reward(win, role);
+        }
+    }
+
+    evaluate(state, role)
+}
+
+fn win_reward(win: Win, role: PlayerRole) -> f32 {
+    if win.role == role {
+        1.0
+    } else if win.role == PlayerRole::Tie {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+/// Scores the pigs left on each side between 0 and 1, with 0.5 being even
+fn evaluate(state: &GameState, role: PlayerRole) -> f32 {
+    let material = |role| -> f32 {
+        state
+            .board(role)
+            .iter()
+            .filter(|x| x.pig != Pig::Flag)
+            .map(|x| value(x.pig))
+            .sum()
+    };
+    let mine = material(role);
+    let theirs = material(role.opp());
+    if mine + theirs == 0.0 {
+        return 0.5;
+    }
+    0.5 + 0.5 * (mine - theirs) / (mine + theirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{seeded_setup, Board, BoardLayout, Piece, SetupStrategy};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    fn board(pieces: &[(Pig, u8)]) -> Board {
+        pieces
+            .iter()
+            .map(|(pig, loc)| Piece::new(*pig, *loc))
+            .collect()
+    }
+
+    fn config(iterations: u32) -> SearchConfig {
+        SearchConfig {
+            time_budget: Duration::from_secs(60),
+            max_iterations: Some(iterations),
+            rollout_depth: 10,
+            ..SearchConfig::default()
+        }
+    }
+
+    fn lightning_state(seed: u64) -> GameState {
+        let pig_config: HashMap<Pig, u8> = [
+            (Pig::Flag, 1),
+            (Pig::Bomb, 2),
+            (Pig::Scout, 3),
+            (Pig::Miner, 2),
+            (Pig::General, 1),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        let layout = BoardLayout::lightning();
+        let one = seeded_setup(&layout, &pig_config, SetupStrategy::Random, seed).unwrap();
+        let two = seeded_setup(&layout, &pig_config, SetupStrategy::Fortress, seed).unwrap();
+
+        let mut state = GameState::new(one, two, PlayerRole::One, pig_config);
+        state.layout = layout;
+        state
+    }
+
+    #[test]
+    fn determinize_keeps_what_is_known() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut two = board(&[
+            (Pig::Flag, 1),
+            (Pig::Bomb, 2),
+            (Pig::Kingo, 3),
+            (Pig::Scout, 14),
+            (Pig::Miner, 5),
+        ]);
+        two[2].revealed = true;
+        // Moved from 4, so it cannot be the flag or the bomb
+        two[3].moved = true;
+        let state = GameState::new(
+            board(&[(Pig::Flag, 1)]),
+            two.clone(),
+            PlayerRole::One,
+            HashMap::new(),
+        );
+
+        for _ in 0..50 {
+            let guess = determinize(&state, PlayerRole::One, &mut rng);
+            let guess = guess.board(PlayerRole::Two);
+
+            let mut pigs: Vec<i32> = guess.iter().map(|x| x.pig as i32).collect();
+            pigs.sort_unstable();
+            assert_eq!(pigs, vec![0, 3, 4, 5, 12]);
+            assert_eq!(guess[2].pig, Pig::Kingo);
+            assert!(!guess[3].pig.immovable());
+            assert!(guess
+                .iter()
+                .zip(two.iter())
+                .all(|(a, b)| a.location == b.location));
+        }
+        assert_eq!(
+            determinize(&state, PlayerRole::Two, &mut rng).board(PlayerRole::One)[0].pig,
+            Pig::Flag
+        );
+    }
+
+    #[test]
+    fn determinize_remembers_moves() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut state = GameState::new(
+            board(&[(Pig::Flag, 1)]),
+            board(&[(Pig::Flag, 1), (Pig::Miner, 14)]),
+            PlayerRole::Two,
+            HashMap::new(),
+        );
+
+        // The miner moves out and back to the tile it was placed on
+        state
+            .apply_move(PlayerRole::Two, Move::new(14, 24))
+            .unwrap();
+        state.current_turn = PlayerRole::Two;
+        state
+            .apply_move(PlayerRole::Two, Move::new(24, 14))
+            .unwrap();
+        let miner = &state.board(PlayerRole::Two)[1];
+        assert_eq!(miner.location, 14);
+        assert!(miner.moved);
+
+        for _ in 0..20 {
+            let guess = determinize(&state, PlayerRole::One, &mut rng);
+            assert_eq!(guess.board(PlayerRole::Two)[0].pig, Pig::Flag);
+            assert_eq!(guess.board(PlayerRole::Two)[1].pig, Pig::Miner);
+        }
+    }
+
+    #[test]
+    fn search_is_reproducible() {
+        let state = lightning_state(11);
+        let first = search(
+            &state,
+            PlayerRole::One,
+            &config(200),
+            &mut StdRng::seed_from_u64(42),
+        );
+        let second = search(
+            &state,
+            PlayerRole::One,
+            &config(200),
+            &mut StdRng::seed_from_u64(42),
+        );
+        assert!(first.is_some());
+        assert_eq!(first, second);
+        assert_eq!(state.validate_move(PlayerRole::One, first.unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn search_captures_the_flag() {
+        let mut rng = StdRng::seed_from_u64(9);
+        // The only hidden enemy pig is the flag, at 22 in the perspective of player one
+        let mut state = GameState::new(
+            board(&[(Pig::Flag, 1), (Pig::Miner, 12), (Pig::Scout, 30)]),
+            board(&[(Pig::Flag, 79), (Pig::Scout, 91)]),
+            PlayerRole::One,
+            [(Pig::Flag, 1)].iter().copied().collect(),
+        );
+        state.board_mut(PlayerRole::Two)[1].revealed = true;
+
+        let mv = search(&state, PlayerRole::One, &config(300), &mut rng).unwrap();
+        let win = state.apply_move(PlayerRole::One, mv).unwrap().win.unwrap();
+        assert_eq!(win.role, PlayerRole::One);
+    }
+
+    #[test]
+    fn respects_time_budget() {
+        let state = lightning_state(3);
+        let config = SearchConfig {
+            time_budget: Duration::from_millis(50),
+            ..SearchConfig::default()
+        };
+
+        let start = Instant::now();
+        let mv = search(
+            &state,
+            PlayerRole::One,
+            &config,
+            &mut StdRng::seed_from_u64(1),
+        );
+        assert!(mv.is_some());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -1,17 +1,39 @@
 mod board;
+mod bot;
 mod interaction;
+mod ismcts;
+mod movegen;
 mod pig;
+mod repetition;
+mod role;
+mod rules;
+mod setup;
+mod state;
 mod test_util;
 
 pub use board::*;
-pub use interaction::InteractionResult;
-pub use pig::{Pig, PigBehavior};
+pub use bot::{choose_move, Difficulty};
+pub use interaction::{
+    resolve_attack, resolve_attack_with, resolve_infiltration, InteractionResult,
+};
+pub use ismcts::{search, SearchConfig};
+pub use movegen::{legal_moves, targets_from};
+pub use pig::{Ability, Pig, PigBehavior};
+pub use repetition::{MoveHistory, RepetitionRule};
+pub use role::PlayerRole;
+pub use rules::RuleSet;
+pub use setup::{generate_setup, seeded_setup, SetupStrategy};
+pub use state::{Attack, GameState, Move, MoveError, MoveOutcome, Win, WinReason};
 
 #[derive(Debug, Clone)]
 pub struct Piece {
     pub pig: Pig,
     pub location: u8,
     pub id: u8,
+    /// Whether the opponent has seen this pig through an attack
+    pub revealed: bool,
+    /// Whether this pig ever left the tile it was placed on
+    pub moved: bool,
 }
 
 impl Piece {
@@ -20,10 +42,13 @@ impl Piece {
             pig,
             location,
             id: location,
+            revealed: false,
+            moved: false,
         }
     }
 
     pub fn move_to(&mut self, location: u8) {
         self.location = location;
+        self.moved = true;
     }
 }
@@ -0,0 +1,169 @@
+use crate::board::{get_scout, pig_in_path, sum_boards};
+use crate::role::PlayerRole;
+use crate::state::{GameState, Move};
+
+/// Generates every move `role` could make in `state`, regardless of whose turn it is.
+/// Infiltrations are not listed separately, since they share the moves of regular attacks.
+pub fn legal_moves(state: &GameState, role: PlayerRole) -> Vec<Move> {
+    let local_board = state.board(role);
+    let layout = &state.layout;
+    let enemy_board = state.enemy_board(role);
+    let total_board = sum_boards(local_board, &enemy_board);
+    let mut moves = Vec::new();
+
+    for piece in local_board.iter() {
+        let behavior = piece.pig.behavior(&state.rules);
+        // Every move is in a straight line, which the scout path covers up to the water tiles
+        for to in get_scout(layout, piece.location) {
+            if !behavior.allow_move(layout, piece.location, to)
+                || local_board.iter().any(|x| x.location == to)
+                || (enemy_board.iter().any(|x| x.location == to)
+                    && !behavior.allow_attack(layout, piece.location, to))
+                || pig_in_path(layout, &total_board, piece.location, to)
+                || state.is_repetition(role, Move::new(piece.location, to))
+            {
+                continue;
+            }
+            moves.push(Move::new(piece.location, to));
+        }
+    }
+
+    moves
+}
+
+/// Lists the tiles the pig of `role` at `from` can move to or attack,
+/// which is empty if there is no such pig or it cannot move.
+pub fn targets_from(state: &GameState, role: PlayerRole, from: u8) -> Vec<u8> {
+    legal_moves(state, role)
+        .into_iter()
+        .filter(|x| x.from == from)
+        .map(|x| x.to)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Board, BoardLayout, Piece, Pig};
+    use std::collections::HashMap;
+
+    fn board(pieces: &[(Pig, u8)]) -> Board {
+        pieces
+            .iter()
+            .map(|(pig, loc)| Piece::new(*pig, *loc))
+            .collect()
+    }
+
+    fn state(one: &[(Pig, u8)], two: &[(Pig, u8)]) -> GameState {
+        let mut config = HashMap::new();
+        config.insert(Pig::Flag, 1);
+        GameState::new(board(one), board(two), PlayerRole::One, config)
+    }
+
+    fn perft(state: &GameState, depth: u32) -> usize {
+        if depth == 0 {
+            return 1;
+        }
+
+        let role = state.current_turn;
+        legal_moves(state, role)
+            .into_iter()
+            .map(|mv| {
+                let mut next = state.clone();
+                let outcome = next.apply_move(role, mv).unwrap();
+                if outcome.win.is_some() {
+                    return 1;
+                }
+                perft(&next, depth - 1)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn perft_miners() {
+        let s = state(
+            &[(Pig::Flag, 1), (Pig::Miner, 2)],
+            &[(Pig::Flag, 1), (Pig::Miner, 2)],
+        );
+
+        assert_eq!(perft(&s, 1), 2);
+        assert_eq!(perft(&s, 2), 4);
+        // From 3 the miner has 3 moves, from 12 it has 4
+        assert_eq!(perft(&s, 3), 14);
+    }
+
+    #[test]
+    fn scout_moves() {
+        // Enemy sergeant sits at 65 in the perspective of player one
+        let s = state(
+            &[(Pig::Flag, 1), (Pig::Scout, 5)],
+            &[(Pig::Flag, 1), (Pig::Sergeant, 36)],
+        );
+
+        let moves = legal_moves(&s, PlayerRole::One);
+        // 6 to 10 on the right, 2 to 4 on the left and 15 up to the sergeant at 65
+        assert_eq!(moves.len(), 14);
+        assert!(moves.contains(&Move::new(5, 65)));
+        assert!(!moves.contains(&Move::new(5, 75)));
+        assert!(!moves.contains(&Move::new(5, 1)));
+        assert_eq!(perft(&s, 1), 14);
+
+        let targets = targets_from(&s, PlayerRole::One, 5);
+        assert_eq!(targets.len(), 14);
+        assert!(targets.contains(&65));
+        assert!(targets_from(&s, PlayerRole::One, 1).is_empty());
+        assert!(targets_from(&s, PlayerRole::One, 6).is_empty());
+    }
+
+    #[test]
+    fn stops_at_water_and_board_edge() {
+        let s = state(
+            &[(Pig::Flag, 1), (Pig::Scout, 42), (Pig::Miner, 90)],
+            &[(Pig::Flag, 1)],
+        );
+
+        let moves = legal_moves(&s, PlayerRole::One);
+        let scout: Vec<_> = moves.iter().filter(|x| x.from == 42).collect();
+        // 41 on the left, 43 is water, 52 to 92 up and 32 to 2 down
+        assert_eq!(scout.len(), 10);
+        // Enemy flag sits in the top right corner
+        assert!(moves.contains(&Move::new(90, 100)));
+        assert!(!moves.iter().any(|x| x.from == 1));
+    }
+
+    #[test]
+    fn generated_moves_are_valid() {
+        let s = state(
+            &[
+                (Pig::Flag, 1),
+                (Pig::Bomb, 2),
+                (Pig::Scout, 12),
+                (Pig::Kingo, 46),
+                (Pig::Spy, 33),
+            ],
+            &[(Pig::Flag, 1), (Pig::Scout, 45), (Pig::Bomb, 69)],
+        );
+
+        let moves = legal_moves(&s, PlayerRole::One);
+        assert!(!moves.is_empty());
+        for mv in moves {
+            assert_eq!(s.validate_move(PlayerRole::One, mv), Ok(()));
+        }
+    }
+
+    #[test]
+    fn lightning_board() {
+        let mut s = state(&[(Pig::Flag, 1), (Pig::Scout, 26)], &[(Pig::Flag, 1)]);
+        s.layout = BoardLayout::lightning();
+
+        let moves = legal_moves(&s, PlayerRole::One);
+        assert_eq!(moves.len(), 8);
+        assert!(!moves.contains(&Move::new(26, 27)));
+        assert!(s
+            .enemy_board(PlayerRole::One)
+            .iter()
+            .any(|x| x.location == 64));
+        // Player two is out of moves, so every move ends the game
+        assert_eq!(perft(&s, 2), 8);
+    }
+}
@@ -1,5 +1,6 @@
-use crate::board::{get_adjacent, get_scout};
+use crate::board::{get_adjacent, get_scout, BoardLayout};
 use crate::interaction::InteractionResult;
+use crate::rules::RuleSet;
 
 macro_rules! rank_eval {
     ($me:expr, $target:expr) => {{
@@ -16,8 +17,12 @@ macro_rules! rank_eval {
 }
 
 pub trait PigBehavior {
-    fn allow_move(&self, from: u8, to: u8) -> bool {
-        get_adjacent(from).contains(&to)
+    fn allow_move(&self, layout: &BoardLayout, from: u8, to: u8) -> bool {
+        get_adjacent(layout, from).contains(&to)
+    }
+    /// Whether a pig that moved from `from` can attack the pig at `to`
+    fn allow_attack(&self, _layout: &BoardLayout, _from: u8, _to: u8) -> bool {
+        true
     }
     fn attack(&self, me: Pig, target: Pig) -> InteractionResult {
         rank_eval!(me, target)
@@ -25,6 +30,38 @@ pub trait PigBehavior {
     fn defense_override(&self, _attacker: Pig) -> Option<InteractionResult> {
         None
     }
+    /// Resolves an attack where the initiator names the pig it expects to find,
+    /// or returns `None` if this pig is unable to infiltrate
+    fn infiltrate(&self, _target: Pig, _guess: Pig) -> Option<InteractionResult> {
+        None
+    }
+    /// Checked after `attack` and `defense_override`, a triggered ability
+    /// turns the attack into a win for the initiator
+    fn ability(&self, _target: Pig, _result: InteractionResult) -> Option<Ability> {
+        None
+    }
+}
+
+/// Special abilities that can change the result of an attack
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Ability {
+    /// Attacking a bomb defuses it, as a miner would
+    NeutralizeBomb = 1,
+    /// Ties become wins for the attacker
+    ConvertTie = 2,
+}
+
+impl Ability {
+    pub const MAX: u32 = 2;
+
+    /// Converts a settings value, where 0 disables the ability
+    pub fn from(val: u32) -> Option<Self> {
+        match val {
+            1 => Some(Self::NeutralizeBomb),
+            2 => Some(Self::ConvertTie),
+            _ => None,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -47,19 +84,27 @@ pub enum Pig {
 }
 
 // Special pigs
-struct Bomb;
+struct Bomb {
+    single_use: bool,
+}
 impl PigBehavior for Bomb {
-    fn allow_move(&self, _from: u8, _to: u8) -> bool {
+    fn allow_move(&self, _layout: &BoardLayout, _from: u8, _to: u8) -> bool {
         false
     }
     fn defense_override(&self, attacker: Pig) -> Option<InteractionResult> {
         if attacker == Pig::Miner {
             return Some(InteractionResult::Lose);
         }
+        // Both pigs are removed, just like a tie
+        if self.single_use {
+            return Some(InteractionResult::Tie);
+        }
         Some(InteractionResult::Win)
     }
 }
-struct Spy;
+struct Spy {
+    attack_only: bool,
+}
 impl PigBehavior for Spy {
     fn attack(&self, me: Pig, target: Pig) -> InteractionResult {
         if target == Pig::Kingo {
@@ -67,20 +112,40 @@ impl PigBehavior for Spy {
         }
         rank_eval!(me, target)
     }
+    fn defense_override(&self, attacker: Pig) -> Option<InteractionResult> {
+        if !self.attack_only && attacker == Pig::Kingo {
+            return Some(InteractionResult::Win);
+        }
+        None
+    }
+}
+struct Infiltrator;
+impl PigBehavior for Infiltrator {
+    fn infiltrate(&self, target: Pig, guess: Pig) -> Option<InteractionResult> {
+        if target == guess {
+            return Some(InteractionResult::Win);
+        }
+        Some(InteractionResult::Lose)
+    }
 }
 struct Flag;
 impl PigBehavior for Flag {
-    fn allow_move(&self, _from: u8, _to: u8) -> bool {
+    fn allow_move(&self, _layout: &BoardLayout, _from: u8, _to: u8) -> bool {
         false
     }
     fn defense_override(&self, _attacker: Pig) -> Option<InteractionResult> {
         Some(InteractionResult::Lose)
     }
 }
-struct Scout;
+struct Scout {
+    move_and_strike: bool,
+}
 impl PigBehavior for Scout {
-    fn allow_move(&self, from: u8, to: u8) -> bool {
-        get_scout(from).contains(&to)
+    fn allow_move(&self, layout: &BoardLayout, from: u8, to: u8) -> bool {
+        get_scout(layout, from).contains(&to)
+    }
+    fn allow_attack(&self, layout: &BoardLayout, from: u8, to: u8) -> bool {
+        self.move_and_strike || get_adjacent(layout, from).contains(&to)
     }
 }
 
@@ -91,8 +156,20 @@ struct Sergeant;
 impl PigBehavior for Sergeant {}
 struct Lieutenant;
 impl PigBehavior for Lieutenant {}
-struct Chemist;
-impl PigBehavior for Chemist {}
+struct Chemist(Option<Ability>);
+impl PigBehavior for Chemist {
+    fn ability(&self, target: Pig, result: InteractionResult) -> Option<Ability> {
+        let ability = self.0?;
+        let triggered = match ability {
+            Ability::NeutralizeBomb => target == Pig::Bomb,
+            Ability::ConvertTie => result == InteractionResult::Tie,
+        };
+        if triggered && result != InteractionResult::Win {
+            return Some(ability);
+        }
+        None
+    }
+}
 struct Major;
 impl PigBehavior for Major {}
 struct Colonel;
@@ -123,6 +200,27 @@ impl Pig {
         }
     }
 
+    /// Looks up a pig by its lowercase name, as used in mode definitions
+    pub fn from_name(name: &str) -> Option<Pig> {
+        let pig = match name {
+            "bomb" => Pig::Bomb,
+            "spy" => Pig::Spy,
+            "infiltrator" => Pig::Infiltrator,
+            "flag" => Pig::Flag,
+            "scout" => Pig::Scout,
+            "miner" => Pig::Miner,
+            "sergeant" => Pig::Sergeant,
+            "lieutenant" => Pig::Lieutenant,
+            "chemist" => Pig::Chemist,
+            "major" => Pig::Major,
+            "colonel" => Pig::Colonel,
+            "general" => Pig::General,
+            "kingo" => Pig::Kingo,
+            _ => return None,
+        };
+        Some(pig)
+    }
+
     pub fn rank(&self) -> u8 {
         match self {
             Pig::Bomb => 0,
@@ -143,23 +241,31 @@ impl Pig {
     }
 
     pub fn immovable(&self) -> bool {
-        match self {
-            Pig::Bomb | Pig::Flag => true,
-            _ => false,
-        }
+        matches!(self, Pig::Bomb | Pig::Flag)
     }
 
     pub fn get_behavior(&self) -> Box<dyn PigBehavior> {
+        self.behavior(&RuleSet::default())
+    }
+
+    /// Gets the behavior of this pig under the house rules of a room
+    pub fn behavior(&self, rules: &RuleSet) -> Box<dyn PigBehavior> {
         match self {
-            Pig::Bomb => Box::new(Bomb),
-            Pig::Spy => Box::new(Spy),
-            Pig::Infiltrator => Box::new(Spy),
+            Pig::Bomb => Box::new(Bomb {
+                single_use: rules.single_use_bombs,
+            }),
+            Pig::Spy => Box::new(Spy {
+                attack_only: rules.spy_attack_only,
+            }),
+            Pig::Infiltrator => Box::new(Infiltrator),
             Pig::Flag => Box::new(Flag),
-            Pig::Scout => Box::new(Scout),
+            Pig::Scout => Box::new(Scout {
+                move_and_strike: rules.scout_move_and_strike,
+            }),
             Pig::Miner => Box::new(Miner),
             Pig::Sergeant => Box::new(Sergeant),
             Pig::Lieutenant => Box::new(Lieutenant),
-            Pig::Chemist => Box::new(Chemist),
+            Pig::Chemist => Box::new(Chemist(rules.chemist)),
             Pig::Major => Box::new(Major),
             Pig::Colonel => Box::new(Colonel),
             Pig::General => Box::new(General),
@@ -0,0 +1,142 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::board::Board;
+use crate::role::PlayerRole;
+use crate::state::Move;
+
+/// Number of moves in a row a pig can make between the same two tiles
+const TWO_SQUARE_LIMIT: usize = 3;
+/// Number of times a position can be reached before moving into it again is rejected
+const POSITION_LIMIT: u8 = 2;
+
+/// Stops players from stalling by moving the same pigs back and forth
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RepetitionRule {
+    Off = 0,
+    /// A pig can only move back and forth between the same two tiles three times in a row
+    TwoSquare = 1,
+    /// A position can only be reached twice with the same side to move
+    Position = 2,
+}
+
+impl RepetitionRule {
+    pub const MAX: u32 = 2;
+
+    pub fn from(val: u32) -> Self {
+        match val {
+            1 => Self::TwoSquare,
+            2 => Self::Position,
+            _ => Self::Off,
+        }
+    }
+}
+
+/// Recent moves made by each side and the positions they led to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoveHistory {
+    /// The last moves of each side, as many as the two square rule looks back at
+    moves: [Vec<Move>; 2],
+    /// Times each position was reached since the last attack,
+    /// as no position before an attack can come up again
+    positions: HashMap<u64, u8>,
+}
+
+impl MoveHistory {
+    pub fn moves(&self, role: PlayerRole) -> &[Move] {
+        &self.moves[index(role)]
+    }
+
+    /// Whether `mv` breaks `rule`, where `next` is the position the move leads to
+    /// if it is not an attack
+    pub(crate) fn is_repetition(
+        &self,
+        rule: RepetitionRule,
+        role: PlayerRole,
+        mv: Move,
+        next: Option<u64>,
+    ) -> bool {
+        match rule {
+            RepetitionRule::Off => false,
+            RepetitionRule::TwoSquare => {
+                let moves = self.moves(role);
+                if moves.len() < TWO_SQUARE_LIMIT {
+                    return false;
+                }
+
+                // Going back in time, moves alternate between the reverse of `mv` and `mv`
+                moves[moves.len() - TWO_SQUARE_LIMIT..]
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .all(|(i, x)| {
+                        if i % 2 == 0 {
+                            x.from == mv.to && x.to == mv.from
+                        } else {
+                            x.from == mv.from && x.to == mv.to
+                        }
+                    })
+            }
+            RepetitionRule::Position => next.is_some_and(|hash| {
+                self.positions.get(&hash).copied().unwrap_or(0) >= POSITION_LIMIT
+            }),
+        }
+    }
+
+    pub(crate) fn record(&mut self, role: PlayerRole, mv: Move, position: u64, attack: bool) {
+        let moves = &mut self.moves[index(role)];
+        if moves.len() == TWO_SQUARE_LIMIT {
+            moves.remove(0);
+        }
+        moves.push(mv);
+        if attack {
+            self.positions.clear();
+        }
+        *self.positions.entry(position).or_insert(0) += 1;
+    }
+}
+
+/// Hashes both boards, each in the perspective of its owner, along with the side to move
+pub(crate) fn position_hash(boards: &[Board; 2], current_turn: PlayerRole) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for board in boards.iter() {
+        let mut pieces: Vec<(u8, i32)> = board.iter().map(|x| (x.location, x.pig as i32)).collect();
+        pieces.sort_unstable();
+        pieces.hash(&mut hasher);
+    }
+    (current_turn as i32).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn index(role: PlayerRole) -> usize {
+    match role {
+        PlayerRole::Two => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_what_cannot_repeat() {
+        let mut history = MoveHistory::default();
+        for i in 0..10 {
+            history.record(
+                PlayerRole::One,
+                Move::new(i + 1, i + 2),
+                u64::from(i % 2),
+                false,
+            );
+        }
+        assert_eq!(history.moves(PlayerRole::One).len(), TWO_SQUARE_LIMIT);
+        assert_eq!(history.moves(PlayerRole::One)[0], Move::new(8, 9));
+        assert_eq!(history.positions.len(), 2);
+
+        history.record(PlayerRole::Two, Move::new(21, 11), 5, true);
+        assert_eq!(history.positions.len(), 1);
+        assert_eq!(history.positions[&5], 1);
+    }
+}
@@ -0,0 +1,16 @@
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum PlayerRole {
+    One = 1,
+    Two = 2,
+    Tie = -1,
+}
+
+impl PlayerRole {
+    pub fn opp(&self) -> Self {
+        match self {
+            Self::One => Self::Two,
+            Self::Two => Self::One,
+            _ => Self::Tie,
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use crate::pig::Ability;
+use crate::repetition::RepetitionRule;
+
+/// House rules that change how pigs move and fight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RuleSet {
+    /// Scouts can attack at the end of a move longer than one tile
+    pub scout_move_and_strike: bool,
+    /// Bombs are removed along with the attacker after defending once
+    pub single_use_bombs: bool,
+    /// Pigs of the same rank are won by the attacker instead of both being removed
+    pub attacker_wins_ties: bool,
+    /// The spy only beats the kingo when attacking it
+    pub spy_attack_only: bool,
+    /// The ability given to chemists, if any
+    pub chemist: Option<Ability>,
+    pub repetition: RepetitionRule,
+}
+
+impl Default for RuleSet {
+    /// The classic rules
+    fn default() -> Self {
+        Self {
+            scout_move_and_strike: true,
+            single_use_bombs: false,
+            attacker_wins_ties: false,
+            spy_attack_only: true,
+            chemist: None,
+            repetition: RepetitionRule::TwoSquare,
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+use crate::board::{get_adjacent, in_starting_bounds, Board, BoardLayout};
+use crate::pig::Pig;
+use crate::Piece;
+
+/// How pigs are arranged within the setup rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SetupStrategy {
+    /// Every pig on a random tile
+    Random,
+    /// The flag in the back row, surrounded by as many bombs as there are,
+    /// with the other pigs placed randomly
+    Fortress,
+}
+
+/// Places every pig of `pig_config` within the setup rows,
+/// or returns `None` if there are more pigs than tiles
+pub fn generate_setup<R: Rng>(
+    layout: &BoardLayout,
+    pig_config: &HashMap<Pig, u8>,
+    strategy: SetupStrategy,
+    rng: &mut R,
+) -> Option<Board> {
+    let mut pigs: Vec<Pig> = pig_config
+        .iter()
+        .filter(|(pig, _amount)| **pig != Pig::Empty)
+        .flat_map(|(pig, amount)| std::iter::repeat_n(*pig, *amount as usize))
+        .collect();
+    if pigs.len() > layout.setup_tiles() as usize {
+        return None;
+    }
+    // Keeps the setup reproducible for a seeded rng, as map order is not
+    pigs.sort_by_key(|x| *x as i32);
+
+    let mut tiles: Vec<u8> = (1..=layout.setup_tiles()).collect();
+    tiles.shuffle(rng);
+    let mut board = Board::new();
+
+    if strategy == SetupStrategy::Fortress {
+        if let Some(i) = pigs.iter().position(|x| *x == Pig::Flag) {
+            pigs.remove(i);
+            let flag = rng.gen_range(1..=layout.width);
+            place(&mut board, &mut tiles, Pig::Flag, flag);
+
+            let mut guards: Vec<u8> = get_adjacent(layout, flag)
+                .into_iter()
+                .filter(|x| in_starting_bounds(layout, *x as i16))
+                .collect();
+            guards.shuffle(rng);
+            for tile in guards {
+                match pigs.iter().position(|x| *x == Pig::Bomb) {
+                    Some(i) => {
+                        pigs.remove(i);
+                        place(&mut board, &mut tiles, Pig::Bomb, tile);
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    for (pig, location) in pigs.into_iter().zip(tiles) {
+        board.push(Piece::new(pig, location));
+    }
+    Some(board)
+}
+
+/// Generates the same setup every time for a given seed
+pub fn seeded_setup(
+    layout: &BoardLayout,
+    pig_config: &HashMap<Pig, u8>,
+    strategy: SetupStrategy,
+    seed: u64,
+) -> Option<Board> {
+    generate_setup(
+        layout,
+        pig_config,
+        strategy,
+        &mut StdRng::seed_from_u64(seed),
+    )
+}
+
+fn place(board: &mut Board, tiles: &mut Vec<u8>, pig: Pig, location: u8) {
+    tiles.retain(|x| *x != location);
+    board.push(Piece::new(pig, location));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> HashMap<Pig, u8> {
+        [
+            (Pig::Flag, 1),
+            (Pig::Bomb, 6),
+            (Pig::Scout, 8),
+            (Pig::Miner, 5),
+            (Pig::Kingo, 1),
+        ]
+        .iter()
+        .copied()
+        .collect()
+    }
+
+    fn pieces(board: &Board) -> Vec<(i32, u8)> {
+        board.iter().map(|x| (x.pig as i32, x.location)).collect()
+    }
+
+    #[test]
+    fn setups_match_config() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for layout in [BoardLayout::standard(), BoardLayout::lightning()].iter() {
+            for strategy in [SetupStrategy::Random, SetupStrategy::Fortress].iter() {
+                let setup = generate_setup(layout, &config(), *strategy, &mut rng).unwrap();
+                assert_eq!(setup.len(), 21);
+                for (pig, amount) in config() {
+                    assert_eq!(
+                        setup.iter().filter(|x| x.pig == pig).count(),
+                        amount as usize
+                    );
+                }
+                assert!(setup
+                    .iter()
+                    .all(|x| in_starting_bounds(layout, x.location as i16)));
+
+                let mut locations: Vec<u8> = setup.iter().map(|x| x.location).collect();
+                locations.sort_unstable();
+                locations.dedup();
+                assert_eq!(locations.len(), 21);
+            }
+        }
+    }
+
+    #[test]
+    fn fortress_guards_the_flag() {
+        let layout = BoardLayout::standard();
+        for seed in 0..20 {
+            let setup = seeded_setup(&layout, &config(), SetupStrategy::Fortress, seed).unwrap();
+            let flag = setup.iter().find(|x| x.pig == Pig::Flag).unwrap().location;
+            assert!(flag <= layout.width);
+
+            for tile in get_adjacent(&layout, flag) {
+                let piece = setup.iter().find(|x| x.location == tile).unwrap();
+                assert_eq!(piece.pig, Pig::Bomb);
+            }
+        }
+
+        // Fewer bombs than tiles next to the flag
+        let few = [(Pig::Flag, 1), (Pig::Bomb, 1), (Pig::Scout, 2)]
+            .iter()
+            .copied()
+            .collect();
+        let setup = seeded_setup(&layout, &few, SetupStrategy::Fortress, 4).unwrap();
+        let flag = setup.iter().find(|x| x.pig == Pig::Flag).unwrap().location;
+        let bomb = setup.iter().find(|x| x.pig == Pig::Bomb).unwrap().location;
+        assert!(get_adjacent(&layout, flag).contains(&bomb));
+    }
+
+    #[test]
+    fn seeded_setups_are_reproducible() {
+        let layout = BoardLayout::standard();
+        for strategy in [SetupStrategy::Random, SetupStrategy::Fortress].iter() {
+            let first = seeded_setup(&layout, &config(), *strategy, 7).unwrap();
+            let second = seeded_setup(&layout, &config(), *strategy, 7).unwrap();
+            let other = seeded_setup(&layout, &config(), *strategy, 8).unwrap();
+            assert_eq!(pieces(&first), pieces(&second));
+            assert_ne!(pieces(&first), pieces(&other));
+        }
+    }
+
+    #[test]
+    fn rejects_crowded_configs() {
+        let crowded = [(Pig::Flag, 1), (Pig::Scout, 24)].iter().copied().collect();
+        assert!(seeded_setup(
+            &BoardLayout::lightning(),
+            &crowded,
+            SetupStrategy::Random,
+            0
+        )
+        .is_none());
+        assert!(
+            seeded_setup(&BoardLayout::standard(), &crowded, SetupStrategy::Random, 0).is_some()
+        );
+    }
+}
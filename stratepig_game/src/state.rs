@@ -0,0 +1,720 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::board::{flip_board, flip_tile, in_bounds, pig_in_path, sum_boards, Board, BoardLayout};
+use crate::interaction::{resolve_attack_with, resolve_infiltration, InteractionResult};
+use crate::movegen::legal_moves;
+use crate::pig::{Ability, Pig};
+use crate::repetition::{position_hash, MoveHistory, RepetitionRule};
+use crate::role::PlayerRole;
+use crate::rules::RuleSet;
+
+/// A move of one piece, given in the perspective of the side making it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub from: u8,
+    pub to: u8,
+    /// The pig an infiltrator expects to find at `to`
+    pub guess: Option<Pig>,
+}
+
+impl Move {
+    pub fn new(from: u8, to: u8) -> Self {
+        Self {
+            from,
+            to,
+            guess: None,
+        }
+    }
+
+    pub fn infiltrate(from: u8, to: u8, guess: Pig) -> Self {
+        Self {
+            from,
+            to,
+            guess: Some(guess),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    WrongTurn,
+    OutOfBounds,
+    NoPiece,
+    FriendlyTarget,
+    NotAllowed,
+    PigInPath,
+    InvalidInfiltration,
+    Repetition,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            Self::WrongTurn => "not at correct turn to allow move",
+            Self::OutOfBounds => "move data not in bounds",
+            Self::NoPiece => "no pig found at from location",
+            Self::FriendlyTarget => "attempting attack on friend piece",
+            Self::NotAllowed => "pig prevents moving in the desired way",
+            Self::PigInPath => "pig found in between to and from locations",
+            Self::InvalidInfiltration => "pig cannot infiltrate the desired location",
+            Self::Repetition => "move repeats previous moves too often",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WinReason {
+    FlagCapture,
+    OutOfMoves,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Win {
+    /// The winning side, or `PlayerRole::Tie` if nobody won
+    pub role: PlayerRole,
+    pub reason: WinReason,
+}
+
+/// Both pigs revealed by an attack and how it ended for the initiator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attack {
+    pub result: InteractionResult,
+    pub initiator: Pig,
+    pub target: Pig,
+    /// The guess made if this attack was an infiltration
+    pub guess: Option<Pig>,
+    /// The ability that decided the result, if any
+    pub ability: Option<Ability>,
+}
+
+impl Attack {
+    pub fn is_infiltration(&self) -> bool {
+        self.guess.is_some()
+    }
+
+    /// Whether an infiltration named the target correctly
+    pub fn guess_correct(&self) -> bool {
+        self.guess == Some(self.target)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveOutcome {
+    pub role: PlayerRole,
+    pub from: u8,
+    pub to: u8,
+    pub attack: Option<Attack>,
+    pub win: Option<Win>,
+}
+
+/// Full state of a game independent of any networking.
+/// Each board is stored in the perspective of the side that owns it,
+/// so both sides place their pigs within the starting territory.
+#[derive(Debug, Clone)]
+pub struct GameState {
+    boards: [Board; 2],
+    pub current_turn: PlayerRole,
+    pub pig_config: HashMap<Pig, u8>,
+    pub rules: RuleSet,
+    pub layout: BoardLayout,
+    pub history: MoveHistory,
+}
+
+impl GameState {
+    pub fn new(
+        one: Board,
+        two: Board,
+        current_turn: PlayerRole,
+        pig_config: HashMap<Pig, u8>,
+    ) -> Self {
+        Self {
+            boards: [one, two],
+            current_turn,
+            pig_config,
+            rules: RuleSet::default(),
+            layout: BoardLayout::standard(),
+            history: MoveHistory::default(),
+        }
+    }
+
+    pub fn board(&self, role: PlayerRole) -> &Board {
+        &self.boards[Self::index(role)]
+    }
+
+    pub(crate) fn board_mut(&mut self, role: PlayerRole) -> &mut Board {
+        &mut self.boards[Self::index(role)]
+    }
+
+    /// The opponent's board flipped into the perspective of `role`
+    pub fn enemy_board(&self, role: PlayerRole) -> Board {
+        flip_board(&self.layout, self.board(role.opp()))
+    }
+
+    pub fn validate_move(&self, role: PlayerRole, mv: Move) -> Result<(), MoveError> {
+        if role != self.current_turn {
+            return Err(MoveError::WrongTurn);
+        }
+        if mv.from == mv.to
+            || !in_bounds(&self.layout, mv.from as i16)
+            || !in_bounds(&self.layout, mv.to as i16)
+        {
+            return Err(MoveError::OutOfBounds);
+        }
+
+        let local_board = self.board(role);
+        let initiator = local_board
+            .iter()
+            .find(|x| x.location == mv.from)
+            .ok_or(MoveError::NoPiece)?;
+
+        if local_board.iter().any(|x| x.location == mv.to) {
+            return Err(MoveError::FriendlyTarget);
+        }
+
+        // Ensure that this is a valid move (ignores rest of pigs on the board)
+        // Prevents jumping over water tiles
+        let behavior = initiator.pig.behavior(&self.rules);
+        if !behavior.allow_move(&self.layout, mv.from, mv.to) {
+            return Err(MoveError::NotAllowed);
+        }
+        let enemy_board = self.enemy_board(role);
+        if enemy_board.iter().any(|x| x.location == mv.to)
+            && !behavior.allow_attack(&self.layout, mv.from, mv.to)
+        {
+            return Err(MoveError::NotAllowed);
+        }
+        // Ensure that there are no pigs (friend or enemy) in between the from and to locations
+        // No actions can be done THROUGH other pigs
+        let total_board = sum_boards(local_board, &enemy_board);
+        if pig_in_path(&self.layout, &total_board, mv.from, mv.to) {
+            return Err(MoveError::PigInPath);
+        }
+
+        // Infiltrations must name a real pig and can only be made against an enemy
+        if let Some(guess) = mv.guess {
+            let target = enemy_board
+                .iter()
+                .find(|x| x.location == mv.to)
+                .ok_or(MoveError::InvalidInfiltration)?;
+            if guess == Pig::Empty
+                || resolve_infiltration(initiator.pig, target.pig, guess).is_none()
+            {
+                return Err(MoveError::InvalidInfiltration);
+            }
+        }
+
+        if self.is_repetition(role, mv) {
+            return Err(MoveError::Repetition);
+        }
+
+        Ok(())
+    }
+
+    /// Whether a move would break the repetition rule, assuming it is otherwise valid
+    pub fn is_repetition(&self, role: PlayerRole, mv: Move) -> bool {
+        let rule = self.rules.repetition;
+        let enemy_tile = flip_tile(&self.layout, mv.to);
+        // Attacks always lead to new positions, and only the position rule needs to know it
+        let next = if rule != RepetitionRule::Position
+            || self
+                .board(role.opp())
+                .iter()
+                .any(|x| x.location == enemy_tile)
+        {
+            None
+        } else {
+            let mut boards = self.boards.clone();
+            if let Some(piece) = boards[Self::index(role)]
+                .iter_mut()
+                .find(|x| x.location == mv.from)
+            {
+                piece.move_to(mv.to);
+            }
+            Some(position_hash(&boards, role.opp()))
+        };
+
+        self.history.is_repetition(rule, role, mv, next)
+    }
+
+    /// Validates and performs a move, passing the turn to the other side
+    pub fn apply_move(&mut self, role: PlayerRole, mv: Move) -> Result<MoveOutcome, MoveError> {
+        self.validate_move(role, mv)?;
+
+        let mut local_board = self.board(role).clone();
+        let mut opponent_board = self.enemy_board(role);
+
+        macro_rules! index {
+            ($loc:expr, $board:expr) => {
+                $board.iter().position(|x| x.location == $loc).unwrap()
+            };
+        }
+
+        let attack = match opponent_board.iter().find(|x| x.location == mv.to) {
+            // Move, not an attack of any sort
+            None => {
+                let i = index!(mv.from, local_board);
+                local_board[i].move_to(mv.to);
+                None
+            }
+            Some(target) => {
+                let initiator = local_board[index!(mv.from, local_board)].pig;
+                let (result, ability) = match mv.guess {
+                    // Already checked to be a valid infiltration
+                    Some(guess) => (
+                        resolve_infiltration(initiator, target.pig, guess).unwrap(),
+                        None,
+                    ),
+                    None => resolve_attack_with(initiator, target.pig, &self.rules),
+                };
+                let attack = Attack {
+                    result,
+                    initiator,
+                    target: target.pig,
+                    guess: mv.guess,
+                    ability,
+                };
+
+                match attack.result {
+                    InteractionResult::Tie => {
+                        local_board.remove(index!(mv.from, local_board));
+                        opponent_board.remove(index!(mv.to, opponent_board));
+                    }
+                    InteractionResult::Win => {
+                        opponent_board.remove(index!(mv.to, opponent_board));
+                        let i = index!(mv.from, local_board);
+                        local_board[i].move_to(mv.to);
+                        local_board[i].revealed = true;
+                    }
+                    InteractionResult::Lose => {
+                        local_board.remove(index!(mv.from, local_board));
+                        let i = index!(mv.to, opponent_board);
+                        opponent_board[i].revealed = true;
+                    }
+                }
+                Some(attack)
+            }
+        };
+
+        self.boards[Self::index(role)] = local_board;
+        self.boards[Self::index(role.opp())] = flip_board(&self.layout, &opponent_board);
+        self.current_turn = role.opp();
+
+        let position = position_hash(&self.boards, self.current_turn);
+        self.history.record(role, mv, position, attack.is_some());
+
+        Ok(MoveOutcome {
+            role,
+            from: mv.from,
+            to: mv.to,
+            attack,
+            win: self.winner(),
+        })
+    }
+
+    /// Checks whether the game is over, either through a captured flag
+    /// or because one or both sides have no pig left that can move
+    pub fn winner(&self) -> Option<Win> {
+        if self.pig_config.get(&Pig::Flag).copied().unwrap_or(0) > 0 {
+            for role in [PlayerRole::One, PlayerRole::Two].iter() {
+                if !self.board(*role).iter().any(|x| x.pig == Pig::Flag) {
+                    return Some(Win {
+                        role: role.opp(),
+                        reason: WinReason::FlagCapture,
+                    });
+                }
+            }
+        }
+
+        let one = !legal_moves(self, PlayerRole::One).is_empty();
+        let two = !legal_moves(self, PlayerRole::Two).is_empty();
+        let role = match (one, two) {
+            (true, true) => return None,
+            (true, false) => PlayerRole::One,
+            (false, true) => PlayerRole::Two,
+            (false, false) => PlayerRole::Tie,
+        };
+
+        Some(Win {
+            role,
+            reason: WinReason::OutOfMoves,
+        })
+    }
+
+    fn index(role: PlayerRole) -> usize {
+        match role {
+            PlayerRole::Two => 1,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{legal_moves, Piece};
+
+    fn board(pieces: &[(Pig, u8)]) -> Board {
+        pieces
+            .iter()
+            .map(|(pig, loc)| Piece::new(*pig, *loc))
+            .collect()
+    }
+
+    fn config() -> HashMap<Pig, u8> {
+        let mut map = HashMap::new();
+        map.insert(Pig::Flag, 1);
+        map
+    }
+
+    // Tile 55 for player one is tile 46 for player two
+    fn state(one: &[(Pig, u8)], two: &[(Pig, u8)]) -> GameState {
+        GameState::new(board(one), board(two), PlayerRole::One, config())
+    }
+
+    #[test]
+    fn rejects_invalid_moves() {
+        let s = state(
+            &[
+                (Pig::Flag, 1),
+                (Pig::Miner, 2),
+                (Pig::Scout, 11),
+                (Pig::Bomb, 12),
+            ],
+            &[(Pig::Flag, 1), (Pig::Miner, 2)],
+        );
+
+        assert_eq!(
+            s.validate_move(PlayerRole::Two, Move::new(2, 12)),
+            Err(MoveError::WrongTurn)
+        );
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::new(2, 2)),
+            Err(MoveError::OutOfBounds)
+        );
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::new(3, 4)),
+            Err(MoveError::NoPiece)
+        );
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::new(2, 12)),
+            Err(MoveError::FriendlyTarget)
+        );
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::new(2, 13)),
+            Err(MoveError::NotAllowed)
+        );
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::new(12, 13)),
+            Err(MoveError::NotAllowed)
+        );
+        assert_eq!(s.validate_move(PlayerRole::One, Move::new(11, 31)), Ok(()));
+    }
+
+    #[test]
+    fn scout_cannot_pass_through_pigs() {
+        let s = state(
+            &[(Pig::Flag, 1), (Pig::Scout, 21)],
+            &[(Pig::Flag, 1), (Pig::Miner, 70)],
+        );
+
+        // Enemy miner sits at 31 from the perspective of player one
+        assert_eq!(s.validate_move(PlayerRole::One, Move::new(21, 31)), Ok(()));
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::new(21, 41)),
+            Err(MoveError::PigInPath)
+        );
+    }
+
+    #[test]
+    fn attack_outcomes() {
+        let mut s = state(
+            &[(Pig::Flag, 1), (Pig::General, 46), (Pig::Miner, 21)],
+            &[(Pig::Flag, 1), (Pig::Colonel, 45), (Pig::Bomb, 60)],
+        );
+
+        // General beats colonel (56 in the perspective of player one)
+        let outcome = s.apply_move(PlayerRole::One, Move::new(46, 56)).unwrap();
+        let attack = outcome.attack.unwrap();
+        assert_eq!(attack.result, InteractionResult::Win);
+        assert_eq!(attack.initiator, Pig::General);
+        assert_eq!(attack.target, Pig::Colonel);
+        assert!(s.board(PlayerRole::One).iter().any(|x| x.location == 56));
+        assert!(!s
+            .board(PlayerRole::Two)
+            .iter()
+            .any(|x| x.pig == Pig::Colonel));
+        assert_eq!(s.current_turn, PlayerRole::Two);
+
+        s.current_turn = PlayerRole::One;
+        let outcome = s.apply_move(PlayerRole::One, Move::new(21, 31)).unwrap();
+        assert!(outcome.attack.is_none());
+
+        // Miner defuses the bomb (41 in the perspective of player one)
+        s.current_turn = PlayerRole::One;
+        let outcome = s.apply_move(PlayerRole::One, Move::new(31, 41)).unwrap();
+        assert_eq!(outcome.attack.unwrap().result, InteractionResult::Win);
+    }
+
+    #[test]
+    fn flag_capture_wins() {
+        let mut s = state(
+            &[(Pig::Flag, 1), (Pig::Scout, 90)],
+            &[(Pig::Flag, 1), (Pig::Miner, 30)],
+        );
+
+        let outcome = s.apply_move(PlayerRole::One, Move::new(90, 100)).unwrap();
+        assert_eq!(
+            outcome.win,
+            Some(Win {
+                role: PlayerRole::One,
+                reason: WinReason::FlagCapture,
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_moves() {
+        let mut s = state(
+            &[(Pig::Flag, 1), (Pig::Bomb, 2), (Pig::Sergeant, 11)],
+            &[(Pig::Flag, 1), (Pig::Kingo, 80)],
+        );
+        assert_eq!(s.winner(), None);
+
+        // Kingo takes the sergeant (11 in the perspective of player one)
+        s.current_turn = PlayerRole::Two;
+        let outcome = s.apply_move(PlayerRole::Two, Move::new(80, 90)).unwrap();
+        assert_eq!(outcome.attack.unwrap().result, InteractionResult::Win);
+        assert_eq!(
+            outcome.win,
+            Some(Win {
+                role: PlayerRole::Two,
+                reason: WinReason::OutOfMoves,
+            })
+        );
+    }
+
+    #[test]
+    fn infiltration() {
+        let mut s = state(
+            &[
+                (Pig::Flag, 1),
+                (Pig::Infiltrator, 46),
+                (Pig::Infiltrator, 42),
+            ],
+            &[(Pig::Flag, 1), (Pig::Kingo, 45), (Pig::Sergeant, 49)],
+        );
+
+        // Correct guess takes out the kingo (56 in the perspective of player one)
+        let outcome = s
+            .apply_move(PlayerRole::One, Move::infiltrate(46, 56, Pig::Kingo))
+            .unwrap();
+        let attack = outcome.attack.unwrap();
+        assert_eq!(attack.result, InteractionResult::Win);
+        assert!(attack.is_infiltration());
+        assert!(attack.guess_correct());
+        assert!(!s.board(PlayerRole::Two).iter().any(|x| x.pig == Pig::Kingo));
+        assert!(s.board(PlayerRole::One).iter().any(|x| x.location == 56));
+
+        // Wrong guess loses the infiltrator, even against a weaker pig
+        s.current_turn = PlayerRole::One;
+        let outcome = s
+            .apply_move(PlayerRole::One, Move::infiltrate(42, 52, Pig::Major))
+            .unwrap();
+        let attack = outcome.attack.unwrap();
+        assert_eq!(attack.result, InteractionResult::Lose);
+        assert!(!attack.guess_correct());
+        assert_eq!(attack.target, Pig::Sergeant);
+        assert!(!s.board(PlayerRole::One).iter().any(|x| x.location == 42));
+        assert!(s
+            .board(PlayerRole::Two)
+            .iter()
+            .any(|x| x.pig == Pig::Sergeant));
+    }
+
+    #[test]
+    fn rejects_invalid_infiltration() {
+        let s = state(
+            &[(Pig::Flag, 1), (Pig::Infiltrator, 46), (Pig::General, 42)],
+            &[(Pig::Flag, 1), (Pig::Kingo, 45), (Pig::Sergeant, 49)],
+        );
+
+        // Only infiltrators may guess
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::infiltrate(42, 52, Pig::Sergeant)),
+            Err(MoveError::InvalidInfiltration)
+        );
+        // Nothing to infiltrate on an empty tile
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::infiltrate(46, 36, Pig::Kingo)),
+            Err(MoveError::InvalidInfiltration)
+        );
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::infiltrate(46, 56, Pig::Empty)),
+            Err(MoveError::InvalidInfiltration)
+        );
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::infiltrate(46, 56, Pig::Spy)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn chemist_abilities() {
+        let pieces = [(Pig::Flag, 1), (Pig::Chemist, 46), (Pig::Chemist, 42)];
+        let enemy = [(Pig::Flag, 1), (Pig::Bomb, 45), (Pig::Chemist, 49)];
+
+        // Without an ability the chemist is a plain rank 6 pig
+        let mut s = state(&pieces, &enemy);
+        let outcome = s.apply_move(PlayerRole::One, Move::new(46, 56)).unwrap();
+        assert_eq!(outcome.attack.unwrap().result, InteractionResult::Lose);
+        assert_eq!(outcome.attack.unwrap().ability, None);
+
+        let mut s = state(&pieces, &enemy);
+        s.rules.chemist = Some(Ability::NeutralizeBomb);
+        let outcome = s.apply_move(PlayerRole::One, Move::new(46, 56)).unwrap();
+        let attack = outcome.attack.unwrap();
+        assert_eq!(attack.result, InteractionResult::Win);
+        assert_eq!(attack.ability, Some(Ability::NeutralizeBomb));
+        assert!(!s.board(PlayerRole::Two).iter().any(|x| x.pig == Pig::Bomb));
+
+        // Neutralizing bombs does nothing for ties
+        s.current_turn = PlayerRole::One;
+        let outcome = s.apply_move(PlayerRole::One, Move::new(42, 52)).unwrap();
+        assert_eq!(outcome.attack.unwrap().result, InteractionResult::Tie);
+
+        let mut s = state(&pieces, &enemy);
+        s.rules.chemist = Some(Ability::ConvertTie);
+        let outcome = s.apply_move(PlayerRole::One, Move::new(42, 52)).unwrap();
+        let attack = outcome.attack.unwrap();
+        assert_eq!(attack.result, InteractionResult::Win);
+        assert_eq!(attack.ability, Some(Ability::ConvertTie));
+        assert!(s.board(PlayerRole::One).iter().any(|x| x.location == 52));
+    }
+
+    #[test]
+    fn house_rules() {
+        // Enemy pigs at 60 and 59 sit at 41 and 42 in the perspective of player one
+        macro_rules! attack {
+            ($rules:expr, $one:expr, $two:expr, $mv:expr) => {{
+                let mut s = state(&$one, &$two);
+                s.rules = $rules;
+                s.apply_move(PlayerRole::One, $mv)
+                    .map(|x| x.attack.unwrap().result)
+            }};
+        }
+        let classic = RuleSet::default();
+
+        // Sergeant sits at 36
+        let one = [(Pig::Flag, 1), (Pig::Scout, 6)];
+        let two = [(Pig::Flag, 1), (Pig::Sergeant, 65)];
+        let rules = RuleSet {
+            scout_move_and_strike: false,
+            ..classic
+        };
+        assert_eq!(
+            attack!(rules, one, two, Move::new(6, 36)),
+            Err(MoveError::NotAllowed)
+        );
+        let mut s = state(&one, &two);
+        s.rules = rules;
+        assert!(!legal_moves(&s, PlayerRole::One).contains(&Move::new(6, 36)));
+        assert!(legal_moves(&s, PlayerRole::One).contains(&Move::new(6, 26)));
+        assert_eq!(
+            attack!(classic, one, two, Move::new(6, 36)),
+            Ok(InteractionResult::Lose)
+        );
+
+        let one = [(Pig::Flag, 1), (Pig::Kingo, 31)];
+        let two = [(Pig::Flag, 1), (Pig::Bomb, 60)];
+        let rules = RuleSet {
+            single_use_bombs: true,
+            ..classic
+        };
+        assert_eq!(
+            attack!(classic, one, two, Move::new(31, 41)),
+            Ok(InteractionResult::Lose)
+        );
+        assert_eq!(
+            attack!(rules, one, two, Move::new(31, 41)),
+            Ok(InteractionResult::Tie)
+        );
+        // Only ties between ranks go to the attacker
+        let rules = RuleSet {
+            single_use_bombs: true,
+            attacker_wins_ties: true,
+            ..classic
+        };
+        assert_eq!(
+            attack!(rules, one, two, Move::new(31, 41)),
+            Ok(InteractionResult::Tie)
+        );
+
+        let one = [(Pig::Flag, 1), (Pig::Major, 31)];
+        let two = [(Pig::Flag, 1), (Pig::Major, 60)];
+        assert_eq!(
+            attack!(classic, one, two, Move::new(31, 41)),
+            Ok(InteractionResult::Tie)
+        );
+        assert_eq!(
+            attack!(rules, one, two, Move::new(31, 41)),
+            Ok(InteractionResult::Win)
+        );
+
+        let one = [(Pig::Flag, 1), (Pig::Kingo, 31)];
+        let two = [(Pig::Flag, 1), (Pig::Spy, 60)];
+        let rules = RuleSet {
+            spy_attack_only: false,
+            ..classic
+        };
+        assert_eq!(
+            attack!(classic, one, two, Move::new(31, 41)),
+            Ok(InteractionResult::Win)
+        );
+        assert_eq!(
+            attack!(rules, one, two, Move::new(31, 41)),
+            Ok(InteractionResult::Lose)
+        );
+    }
+
+    #[test]
+    fn repetition() {
+        let pieces = [(Pig::Flag, 1), (Pig::Miner, 11)];
+        let shuffle = |s: &mut GameState, count: usize| {
+            for i in 0..count {
+                let (one, two) = if i % 2 == 0 {
+                    (Move::new(11, 21), Move::new(11, 21))
+                } else {
+                    (Move::new(21, 11), Move::new(21, 11))
+                };
+                s.apply_move(PlayerRole::One, one).unwrap();
+                s.apply_move(PlayerRole::Two, two).unwrap();
+            }
+        };
+
+        let mut s = state(&pieces, &pieces);
+        shuffle(&mut s, 3);
+        assert_eq!(s.history.moves(PlayerRole::One).len(), 3);
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::new(21, 11)),
+            Err(MoveError::Repetition)
+        );
+        assert!(!legal_moves(&s, PlayerRole::One).contains(&Move::new(21, 11)));
+        assert_eq!(s.validate_move(PlayerRole::One, Move::new(21, 31)), Ok(()));
+
+        // Positions repeat every two moves, so the third visit is rejected
+        let mut s = state(&pieces, &pieces);
+        s.rules.repetition = RepetitionRule::Position;
+        shuffle(&mut s, 4);
+        assert_eq!(
+            s.validate_move(PlayerRole::One, Move::new(11, 21)),
+            Err(MoveError::Repetition)
+        );
+        assert_eq!(s.validate_move(PlayerRole::One, Move::new(11, 12)), Ok(()));
+
+        let mut s = state(&pieces, &pieces);
+        s.rules.repetition = RepetitionRule::Off;
+        shuffle(&mut s, 10);
+    }
+}
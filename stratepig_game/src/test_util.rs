@@ -2,19 +2,23 @@ use crate::board::*;
 use crate::Piece;
 
 #[allow(dead_code)]
-pub fn print_path(piece: u8, path: Vec<u8>) {
-    for row in (0..10).rev() {
+pub fn print_path(layout: &BoardLayout, piece: u8, path: Vec<u8>) {
+    let width = layout.width;
+    for row in (0..layout.height).rev() {
         let mut row_str = String::new();
-        let in_row: Vec<&u8> = path.iter().filter(|x| (*x - 1) / 10 == row).collect();
-        for col in 1..11 {
-            let values: Vec<&&u8> = in_row.iter().filter(|x| get_column(***x) == col).collect();
+        let in_row: Vec<&u8> = path.iter().filter(|x| (*x - 1) / width == row).collect();
+        for col in 1..width + 1 {
+            let values: Vec<&&u8> = in_row
+                .iter()
+                .filter(|x| get_column(layout, ***x) == col)
+                .collect();
 
-            let tile = 10 * row + col;
-            if values.len() > 0 {
+            let tile = width * row + col;
+            if !values.is_empty() {
                 row_str.push_str(&format!("\x1b[32m{} \x1b[0m", to_double_digit(tile)));
             } else if tile == piece {
                 row_str.push_str("\x1b[35mPP \x1b[0m");
-            } else if WATER_TILES.contains(&tile) {
+            } else if layout.is_lake(tile) {
                 row_str.push_str("\x1b[34mSS \x1b[0m");
             } else {
                 row_str.push_str("00 ");
@@ -26,22 +30,23 @@ pub fn print_path(piece: u8, path: Vec<u8>) {
 }
 
 #[allow(dead_code)]
-pub fn print_board(board: &Board) {
+pub fn print_board(layout: &BoardLayout, board: &Board) {
+    let width = layout.width;
     println!("---------------------");
-    for row in 0..10 {
+    for row in 0..layout.height {
         let mut row_str = String::new();
         let in_row: Vec<&Piece> = board
             .iter()
-            .filter(|x| (x.location - 1) / 10 == row)
+            .filter(|x| (x.location - 1) / width == row)
             .collect();
-        for col in 1..11 {
+        for col in 1..width + 1 {
             let values: Vec<&&Piece> = in_row
                 .iter()
-                .filter(|x| get_column(x.location) == col)
+                .filter(|x| get_column(layout, x.location) == col)
                 .collect();
 
-            let tile = 10 * row + col;
-            if WATER_TILES.contains(&tile) {
+            let tile = width * row + col;
+            if layout.is_lake(tile) {
                 row_str.push_str("\x1b[34mSS \x1b[0m");
             } else if let Some(piece) = values.iter().find(|x| x.location == tile) {
                 row_str.push_str(&format!("\x1b[32m{} \x1b[0m", piece.pig.print()));
@@ -56,10 +61,10 @@ pub fn print_board(board: &Board) {
 
 pub fn to_double_digit(num: u8) -> String {
     if num < 10 {
-        return String::from(format!("0{}", num));
+        format!("0{}", num)
     } else if num >= 100 {
-        return String::from("00");
+        String::from("00")
     } else {
-        return num.to_string();
+        num.to_string()
     }
 }
@@ -1,62 +1,72 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::AttributeArgs;
-use syn::{parse_macro_input, DeriveInput, ItemStruct};
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, AttributeArgs, DeriveInput, Lit, Meta, NestedMeta};
 
+/// Implements `PacketBody` for a packet sent by the server.
+///
+/// Takes the packet id, followed by `variant` if the struct is another layout of a packet
+/// that already has the id, such as the attack and infiltration forms of move data.
 #[proc_macro_attribute]
 pub fn server_packet(args: TokenStream, input: TokenStream) -> TokenStream {
-    let args = parse_macro_input!(args as AttributeArgs)
-        .into_iter()
-        .find(|_x| true)
-        .unwrap();
-    let input = parse_macro_input!(input as ItemStruct);
-    let name = input.clone().ident;
+    let args = parse_macro_input!(args as AttributeArgs);
+    let input = parse_macro_input!(input as DeriveInput);
+    packet(Direction::Server, args, input)
+}
 
-    let expanded = quote! {
-        #[derive(Serialize, Deserialize, Clone, Debug)]
-        #input
+/// Implements `PacketBody` for a packet sent by the client,
+/// taking the same arguments as `server_packet`
+#[proc_macro_attribute]
+pub fn client_packet(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let input = parse_macro_input!(input as DeriveInput);
+    packet(Direction::Client, args, input)
+}
 
-        impl stratepig_core::PacketBody for #name {
-            fn serialize(&self) -> Result<Vec<u8>, stratepig_core::Error> {
-                match bincode::serialize::<Self>(&self) {
-                    Ok(d) => Ok(d),
-                    Err(e) => Err(stratepig_core::Error::InvalidData(e.to_string())),
-                }
-            }
+enum Direction {
+    Server,
+    Client,
+}
 
-            fn deserialize(_data: &[u8]) -> Result<Self, stratepig_core::Error> {
-                panic!("Trying to deserialize a server packet!");
-            }
+fn packet(direction: Direction, args: AttributeArgs, input: DeriveInput) -> TokenStream {
+    let (id, variant) = match parse_args(&args) {
+        Ok(args) => args,
+        Err(err) => return TokenStream::from(err.to_compile_error()),
+    };
+    let name = &input.ident;
 
-            fn id(&self) -> u8 {
-                #args
-            }
+    let (direction, side) = match direction {
+        Direction::Server => (quote!(stratepig_core::Direction::Server), "server"),
+        Direction::Client => (quote!(stratepig_core::Direction::Client), "client"),
+    };
 
-            fn box_clone(&self) -> Box<dyn PacketBody> {
-                Box::new((*self).clone())
-            }
+    // Two packets defining the same marker in a module fails to compile,
+    // which catches ids given to more than one packet by mistake
+    let unique = if variant {
+        quote!()
+    } else {
+        let marker = format_ident!("__duplicate_{}_packet_id_{}", side, id);
+        quote! {
+            #[doc(hidden)]
+            #[allow(non_camel_case_types, dead_code)]
+            struct #marker;
         }
     };
 
-    TokenStream::from(expanded)
-}
-
-#[proc_macro_attribute]
-pub fn client_packet(args: TokenStream, input: TokenStream) -> TokenStream {
-    let args = parse_macro_input!(args as AttributeArgs)
-        .into_iter()
-        .find(|_x| true)
-        .unwrap();
-    let input = parse_macro_input!(input as DeriveInput);
-    let name = input.clone().ident;
-
     let expanded = quote! {
         #[derive(Serialize, Deserialize, Clone, Debug)]
         #input
 
+        impl #name {
+            pub const ID: u8 = #id;
+        }
+
         impl stratepig_core::PacketBody for #name {
             fn serialize(&self) -> Result<Vec<u8>, stratepig_core::Error> {
-                panic!("Trying to serialize a client packet!");
+                match bincode::serialize::<Self>(&self) {
+                    Ok(d) => Ok(d),
+                    Err(e) => Err(stratepig_core::Error::InvalidData(e.to_string())),
+                }
             }
 
             fn deserialize(data: &[u8]) -> Result<Self, stratepig_core::Error> {
@@ -67,14 +77,48 @@ pub fn client_packet(args: TokenStream, input: TokenStream) -> TokenStream {
             }
 
             fn id(&self) -> u8 {
-                #args
+                Self::ID
             }
 
-            fn box_clone(&self) -> Box<dyn PacketBody> {
+            fn box_clone(&self) -> Box<dyn stratepig_core::PacketBody> {
                 Box::new((*self).clone())
             }
         }
+
+        stratepig_core::inventory::submit! {
+            stratepig_core::PacketInfo {
+                id: #id,
+                name: stringify!(#name),
+                direction: #direction,
+                variant: #variant,
+            }
+        }
+
+        #unique
     };
 
     TokenStream::from(expanded)
 }
+
+fn parse_args(args: &[NestedMeta]) -> syn::Result<(u8, bool)> {
+    let id = match args.first() {
+        Some(NestedMeta::Lit(Lit::Int(id))) => id.base10_parse::<u8>()?,
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "expected a packet id from 0 to 255",
+            ))
+        }
+    };
+
+    let variant = match args.get(1) {
+        None => false,
+        Some(NestedMeta::Meta(Meta::Path(path))) if path.is_ident("variant") => true,
+        Some(other) => return Err(syn::Error::new_spanned(other, "expected `variant`")),
+    };
+    if let Some(extra) = args.get(2) {
+        return Err(syn::Error::new_spanned(extra, "unexpected argument"));
+    }
+
+    Ok((id, variant))
+}
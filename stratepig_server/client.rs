@@ -1,38 +1,42 @@
-use crate::player::*;
+use crate::util::gen_session_token;
+use crate::version;
 use crate::Endpoint;
 
 pub struct Client {
     pub id: usize,
     pub endpoint: Endpoint,
+    /// Room that packets of the client are passed on to, or 0 if they are in none
     pub game_room_id: usize,
-    pub room_player: Option<RoomPlayer>,
-    pub player: Option<Player>,
+    /// Lets a new connection take over this client after a drop
+    pub session_token: String,
+    /// When the client is removed if their connection dropped and has not resumed
+    pub reconnect_deadline: Option<u128>,
+    pub reconnect_ticker: Option<tokio::task::JoinHandle<()>>,
+    /// When the last packet was received from the client
+    pub last_seen: u128,
+    /// Round trip time measured from the last keep alive echo
+    pub rtt_ms: Option<u32>,
+    /// Protocol version the client speaks, which is the legacy one until it sends a hello.
+    /// `None` if the server does not speak it.
+    pub protocol: Option<u32>,
 }
 
 impl Client {
-    pub fn new(id: usize, endpoint: Endpoint) -> Self {
+    pub fn new(id: usize, endpoint: Endpoint, now: u128) -> Self {
         Self {
             id,
             endpoint,
             game_room_id: 0,
-            room_player: None,
-            player: None,
+            session_token: gen_session_token(),
+            reconnect_deadline: None,
+            reconnect_ticker: None,
+            last_seen: now,
+            rtt_ms: None,
+            protocol: version::initial_protocol(),
         }
     }
 
     pub fn set_game_room(&mut self, id: usize) {
         self.game_room_id = id;
     }
-
-    pub fn set_player(&mut self, player: Player) {
-        self.player = Some(player);
-    }
-
-    pub fn reset(&mut self) {
-        if self.player.is_none() || self.room_player.is_none() {
-            return;
-        }
-        self.room_player.as_mut().unwrap().reset();
-        self.player.as_mut().unwrap().reset();
-    }
 }
@@ -0,0 +1,154 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::time;
+
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Where the server gets the time from, for timestamps sent to clients
+/// and for the waits of its tickers
+pub trait Clock: Send + Sync {
+    /// Time since the unix epoch
+    fn now(&self) -> Duration;
+
+    /// Waits until `duration` has passed on this clock
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    fn now_millis(&self) -> u128 {
+        self.now().as_millis()
+    }
+
+    fn now_secs(&self) -> u64 {
+        self.now().as_secs()
+    }
+
+    /// Unix timestamp in milliseconds of `duration` from now
+    fn millis_in(&self, duration: Duration) -> u128 {
+        (self.now() + duration).as_millis()
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Wall time, used by the running server
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(time::sleep(duration))
+    }
+}
+
+/// A clock that only moves when told to, so timers can be tested without waiting on them
+#[cfg(test)]
+#[derive(Default)]
+pub struct ManualClock {
+    state: std::sync::Mutex<ManualState>,
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct ManualState {
+    now: Duration,
+    sleepers: Vec<(Duration, tokio::sync::oneshot::Sender<()>)>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(now: Duration) -> Self {
+        Self {
+            state: std::sync::Mutex::new(ManualState {
+                now,
+                sleepers: Vec::new(),
+            }),
+        }
+    }
+
+    /// Moves the clock forward, waking every sleep that is due by then.
+    /// A task that sleeps again once woken only starts that sleep from the new time.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now += duration;
+
+        let now = state.now;
+        let (due, waiting) = state
+            .sleepers
+            .drain(..)
+            .partition(|(deadline, _wake)| *deadline <= now);
+        state.sleepers = waiting;
+        for (_deadline, wake) in due {
+            // The sleep may have been dropped
+            let _ = wake.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        if duration == Duration::ZERO {
+            return Box::pin(async {});
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let (wake, woken) = tokio::sync::oneshot::channel();
+        let deadline = state.now + duration;
+        state.sleepers.push((deadline, wake));
+        Box::pin(async move {
+            let _ = woken.await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    /// Runs a sleep in its own task, returning what it sends once it is over
+    fn sleeping(clock: &ManualClock, secs: u64) -> oneshot::Receiver<()> {
+        let sleep = clock.sleep(Duration::from_secs(secs));
+        let (done, over) = oneshot::channel();
+        tokio::task::spawn(async move {
+            sleep.await;
+            let _ = done.send(());
+        });
+        over
+    }
+
+    #[test]
+    fn advances_by_hand() {
+        let clock = ManualClock::new(Duration::from_secs(100));
+        assert_eq!(clock.now_secs(), 100);
+        assert_eq!(clock.millis_in(Duration::from_secs(5)), 105_000);
+
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(clock.now_millis(), 101_500);
+    }
+
+    #[tokio::test]
+    async fn wakes_due_sleeps() {
+        let clock = ManualClock::default();
+        let mut short = sleeping(&clock, 1);
+        let mut long = sleeping(&clock, 10);
+        assert!(short.try_recv().is_err());
+
+        clock.advance(Duration::from_secs(5));
+        short.await.unwrap();
+        assert!(long.try_recv().is_err());
+
+        clock.advance(Duration::from_secs(5));
+        long.await.unwrap();
+    }
+}
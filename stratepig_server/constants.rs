@@ -1 +1,2 @@
-pub const MAX_USERNAME_LENGTH: u8 = 15;
+/// Time the computer player waits before making its move
+pub const COMPUTER_MOVE_DELAY_MS: u64 = 800;
@@ -1,17 +1,67 @@
+use stratepig_game::MoveError;
+
+/// Why a client packet was not handled, sent to the client in an `ErrorPacket`
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Unknown = 0,
+    BadPacket = 1,
+    Unauthenticated = 2,
+    Incompatible = 3,
+    NotInRoom = 4,
+    NotInGame = 5,
+    WrongState = 6,
+    NotHost = 7,
+    WrongTurn = 8,
+    IllegalMove = 9,
+    BadSetup = 10,
+    BadRequest = 11,
+    NoSession = 12,
+    RateLimited = 13,
+}
+
 #[derive(Debug)]
 pub enum StratepigError {
     Core(stratepig_core::Error),
+    Game(MoveError),
 
     AssumeWrongId,
     MissingContext,
     Unspecified,
     Default(String),
+    Coded(ErrorCode, String),
 }
 
 impl StratepigError {
     pub fn with(msg: &str) -> Self {
         Self::Default(msg.to_owned())
     }
+
+    pub fn coded(code: ErrorCode, msg: &str) -> Self {
+        Self::Coded(code, msg.to_owned())
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Core(_) => ErrorCode::BadPacket,
+            Self::Game(MoveError::WrongTurn) => ErrorCode::WrongTurn,
+            Self::Game(_) => ErrorCode::IllegalMove,
+            Self::AssumeWrongId => ErrorCode::Unauthenticated,
+            Self::MissingContext => ErrorCode::NotInRoom,
+            Self::Unspecified | Self::Default(_) => ErrorCode::Unknown,
+            Self::Coded(code, _msg) => *code,
+        }
+    }
+
+    /// Explanation for the client, if there is more to say than the code
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            Self::Core(err) => Some(err.to_string()),
+            Self::Game(err) => Some(err.to_string()),
+            Self::Default(msg) | Self::Coded(_, msg) => Some(msg.clone()),
+            _ => None,
+        }
+    }
 }
 
 impl From<stratepig_core::Error> for StratepigError {
@@ -19,3 +69,34 @@ impl From<stratepig_core::Error> for StratepigError {
         StratepigError::Core(err)
     }
 }
+
+impl From<stratepig_game::MoveError> for StratepigError {
+    fn from(err: stratepig_game::MoveError) -> StratepigError {
+        StratepigError::Game(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        let err = StratepigError::from(MoveError::WrongTurn);
+        assert_eq!(err.code(), ErrorCode::WrongTurn);
+        assert_eq!(err.detail().unwrap(), MoveError::WrongTurn.to_string());
+        assert_eq!(
+            StratepigError::from(MoveError::PigInPath).code(),
+            ErrorCode::IllegalMove
+        );
+
+        let err = StratepigError::coded(ErrorCode::NotHost, "invalid authority");
+        assert_eq!(err.code(), ErrorCode::NotHost);
+        assert_eq!(err.detail().as_deref(), Some("invalid authority"));
+        assert_eq!(
+            StratepigError::AssumeWrongId.code(),
+            ErrorCode::Unauthenticated
+        );
+        assert_eq!(StratepigError::AssumeWrongId.detail(), None);
+    }
+}
@@ -0,0 +1,22 @@
+use stratepig_game::Move;
+
+use crate::unwrap_ret;
+use crate::GameRoom;
+use crate::StratepigError;
+
+impl GameRoom {
+    /// Plays the move picked by the computer player during its turn
+    pub async fn computer_move(&mut self, mv: Move) -> Result<(), StratepigError> {
+        if self.game_phase != 2 || self.game_ended || !self.is_bot_turn() {
+            return Err(StratepigError::with("not the turn of the computer player"));
+        }
+
+        let mut state = unwrap_ret!(self.game_state());
+        let role = state.current_turn;
+        let outcome = state.apply_move(role, mv)?;
+
+        self.finish_move(&state, &outcome).await;
+
+        Ok(())
+    }
+}
@@ -2,59 +2,47 @@ use std::convert::TryInto;
 use stratepig_core::{Packet, PacketBody};
 
 use crate::packet::FinishedSceneLoadPacket;
-use crate::GameServer;
+use crate::GameRoom;
 use crate::StratepigError;
 
+mod bot;
+#[allow(clippy::module_inception)]
 mod game;
 mod operations;
 mod send;
 mod start;
 mod win;
 
-impl GameServer {
+impl GameRoom {
     pub async fn handle_client_finish_scene_load(
         &mut self,
         id: usize,
         packet: Packet,
     ) -> Result<(), StratepigError> {
         let data = FinishedSceneLoadPacket::deserialize(&packet.body)?;
-        let (_client, room) = self.get_context(id).unwrap();
-        let room_id = room.id();
-
-        drop(room);
 
         if data.scene_index <= 2 {
-            self.all_clients
-                .get_mut(&id)
-                .unwrap()
-                .player
-                .as_mut()
-                .unwrap()
-                .scene_index = data.scene_index.try_into().unwrap_or(2);
+            self.player_mut(id).unwrap().scene_index = data.scene_index.try_into().unwrap_or(2);
         }
 
-        let reference = self.get_room(room_id).unwrap();
-        reference.store_seen();
+        self.store_seen();
+
+        let opp_scene = self.other_member(id).map(|x| x.player.scene_index);
+        let vs_bot = self.settings.bot.is_some();
+        // The computer player is always done loading
+        let opponent_loaded = |scene| match opp_scene {
+            Some(opp_scene) => opp_scene == scene,
+            None => vs_bot,
+        };
 
-        if data.scene_index == 2 {
+        if data.scene_index == 2 && opponent_loaded(2) {
             // Game
-            if let Some(opp) = self.get_other_player(&reference, id) {
-                if opp.player.as_ref().unwrap().scene_index == 2 {
-                    self.both_clients_loaded_game(&reference).await;
-                }
-            } else if self.config.one_player {
-                self.both_clients_loaded_game(&reference).await;
-            }
-        } else if data.scene_index == 1 {
-            if let Some(opp) = self.get_other_player(&reference, id) {
-                if opp.player.as_ref().unwrap().scene_index == 1 {
-                    self.room_player_add(&reference).await;
-                    self.send_game_info(&reference, None).await;
-                }
-            } else if self.config.one_player {
-                self.room_player_add(&reference).await;
-                self.send_game_info(&reference, None).await;
-            }
+            self.both_clients_loaded_game().await;
+            let placement_time = self.settings.placement_time;
+            self.start_placement(placement_time).await;
+        } else if data.scene_index == 1 && opponent_loaded(1) {
+            self.room_player_add().await;
+            self.send_game_info(None).await;
         }
 
         Ok(())
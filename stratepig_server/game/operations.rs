@@ -1,114 +1,62 @@
+use stratepig_game::GameState;
+
 use crate::win::WinType;
+use crate::GameRoom;
 use crate::PlayerRole;
-use crate::{GameRoom, GameServer};
-
-impl GameServer {
-    pub async fn run_operations(&self, room: &GameRoom, is_placement: bool) {
-        if self.config.ignore_turns {
-            return;
-        }
 
-        let client_ids = room.inner().client_ids.clone();
-        let id = client_ids.get(0).unwrap();
-        let player = self.get_client(id.0).unwrap().player.as_ref().unwrap();
-        let opp_player = self
-            .get_client(room.other_id(id.0))
-            .unwrap()
-            .player
-            .as_ref()
-            .unwrap();
+impl GameRoom {
+    /// Builds the rules engine state from the boards of both players in the room
+    pub fn game_state(&self) -> Option<GameState> {
+        let mut one = None;
+        let mut two = None;
 
-        let local_board = player.board.clone();
-        let enemy_board = stratepig_game::flip_board(&opp_player.board);
-        let total_board = stratepig_game::sum_boards(&local_board, &enemy_board);
+        let mut players: Vec<_> = self.members.iter().map(|x| &x.player).collect();
+        players.extend(self.bot.as_ref());
 
-        let mut local_success = false;
-        let mut enemy_success = false;
-
-        'outer: for i in 1..3 {
-            let board;
-            if i == 1 {
-                board = &local_board;
-            } else {
-                board = &enemy_board;
+        for player in players {
+            match player.role {
+                PlayerRole::One => one = Some(player.board.clone()),
+                PlayerRole::Two => two = Some(player.board.clone()),
+                _ => {}
             }
+        }
 
-            for piece in board.into_iter() {
-                if piece.pig.immovable() {
-                    continue;
-                }
-                let pos = piece.location as i16;
-
-                let mut surrounding = vec![pos + 1, pos - 1, pos + 10, pos - 10];
-                surrounding = surrounding
-                    .into_iter()
-                    .filter(|x| *x > 0 && *x < 100)
-                    .collect();
-
-                if pos % 10 == 0 {
-                    surrounding = surrounding.into_iter().filter(|x| *x != pos + 1).collect();
-                }
-                if pos - 1 == 0 || (pos - 1) % 10 == 0 {
-                    surrounding = surrounding.into_iter().filter(|x| *x != pos - 1).collect();
-                }
+        let mut state = GameState::new(
+            one?,
+            two?,
+            self.current_turn,
+            self.settings.pig_config.clone(),
+        );
+        state.rules = self.settings.rules;
+        state.layout = self.modes.layout(self.settings.game_mode);
+        state.history = self.history.clone();
+        Some(state)
+    }
 
-                for tile in surrounding {
-                    let tile = tile as u8;
-                    if stratepig_game::WATER_TILES.contains(&tile) {
-                        continue;
-                    }
-                    // Another pig is here
-                    if total_board.iter().any(|x| x.location == tile) {
-                        if i == 1 {
-                            if enemy_board.iter().any(|x| x.location == tile)
-                                && !local_board.iter().any(|x| x.location == tile)
-                            {
-                                local_success = true;
-                                continue 'outer;
-                            } else {
-                                continue;
-                            }
-                        } else {
-                            if local_board.iter().any(|x| x.location == tile)
-                                && !enemy_board.iter().any(|x| x.location == tile)
-                            {
-                                enemy_success = true;
-                                continue 'outer;
-                            } else {
-                                continue;
-                            }
-                        }
-                    }
+    /// Writes the boards and move history of a state back to the room
+    pub fn store_game_state(&mut self, state: &GameState) {
+        self.history = state.history.clone();
 
-                    if i == 1 {
-                        local_success = true;
-                        continue 'outer;
-                    } else {
-                        enemy_success = true;
-                        continue 'outer;
-                    }
-                }
+        let players = self.members.iter_mut().map(|x| &mut x.player);
+        for player in players.chain(self.bot.as_mut()) {
+            player.board = state.board(player.role).clone();
+        }
+    }
 
-                if i == 1 {
-                    local_success = false;
-                } else {
-                    enemy_success = false;
-                }
-            }
+    pub async fn run_operations(&mut self, is_placement: bool) {
+        if self.config.ignore_turns {
+            return;
         }
 
-        if !(local_success && enemy_success) {
-            room.get().write().unwrap().game_ended = true;
-            if !local_success && enemy_success {
-                self.broadcast_win_i(&room, PlayerRole::Two, WinType::OutOfMoves, is_placement)
-                    .await;
-            } else if !enemy_success && local_success {
-                self.broadcast_win_i(&room, PlayerRole::One, WinType::OutOfMoves, is_placement)
-                    .await;
-            } else {
-                self.broadcast_win_i(&room, PlayerRole::Tie, WinType::OutOfMoves, is_placement)
-                    .await;
-            }
+        let state = match self.game_state() {
+            Some(state) => state,
+            None => return,
+        };
+
+        if let Some(win) = state.winner() {
+            self.game_ended = true;
+            self.broadcast_win_i(win.role, WinType::from(win.reason), is_placement)
+                .await;
         }
     }
 }
@@ -1,18 +1,18 @@
 use crate::*;
 use stratepig_game::*;
 
-impl GameServer {
-    pub async fn both_clients_loaded_game(&self, room: &GameRoom) {
+impl GameRoom {
+    pub async fn both_clients_loaded_game(&self) {
         let packet = BothClientsLoadedGamePacket;
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
-    pub async fn game_player_ready_state(&self, room: &GameRoom, id: usize, ready: bool) {
+    pub async fn game_player_ready_state(&self, id: usize, ready: bool) {
         let packet = GamePlayerUpdatedReadyStatePacket {
             id: id.to_string(),
             ready,
         };
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
     pub async fn opponent_pig_placement(&self, id: usize, locations: Vec<u8>) {
@@ -20,47 +20,75 @@ impl GameServer {
         self.message_one(id, packet).await;
     }
 
-    pub async fn send_move_data(
-        &self,
-        room: &GameRoom,
-        initiator_role: PlayerRole,
-        from: u8,
-        to: u8,
-    ) {
-        let packet = MoveDataPacket {
-            role: initiator_role as u32,
-            from,
-            to,
-            bundle_null: true,
+    pub async fn send_legal_moves(&self, id: usize, location: u8, targets: Vec<u8>) {
+        let packet = LegalMovesPacket { location, targets };
+        self.message_one(id, packet).await;
+    }
+
+    pub async fn send_move_data(&self, outcome: &MoveOutcome) {
+        let role = outcome.role as u32;
+        match outcome.attack {
+            None => {
+                let packet = MoveDataPacket {
+                    role,
+                    from: outcome.from,
+                    to: outcome.to,
+                    bundle_null: true,
+                };
+                self.message_room(packet).await;
+            }
+            Some(attack) if attack.is_infiltration() => {
+                let packet = MoveDataInfiltratePacket {
+                    role,
+                    from: outcome.from,
+                    to: outcome.to,
+                    bundle_null: false,
+                    result: attack.result as i32,
+                    init_type: attack.initiator as u32,
+                    target_type: attack.target as u32,
+                    ability: attack.ability.map_or(0, |x| x as u32),
+                    guess: attack.guess.unwrap() as u32,
+                    correct: attack.guess_correct(),
+                };
+                self.message_room(packet).await;
+            }
+            Some(attack) => {
+                let packet = MoveDataAttackPacket {
+                    role,
+                    from: outcome.from,
+                    to: outcome.to,
+                    bundle_null: false,
+                    result: attack.result as i32,
+                    init_type: attack.initiator as u32,
+                    target_type: attack.target as u32,
+                    ability: attack.ability.map_or(0, |x| x as u32),
+                };
+                self.message_room(packet).await;
+            }
+        }
+    }
+
+    pub async fn send_move_rejected(&self, id: usize, mv: stratepig_game::Move, err: MoveError) {
+        let packet = MoveRejectedPacket {
+            from: mv.from,
+            to: mv.to,
+            msg: err.to_string(),
         };
-        self.message_room(room, packet).await;
+        self.message_one(id, packet).await;
     }
 
-    pub async fn send_move_data_attack(
-        &self,
-        room: &GameRoom,
-        initiator_role: PlayerRole,
-        from: u8,
-        to: u8,
-        result: InteractionResult,
-        init_type: Pig,
-        target_type: Pig,
-    ) {
-        let packet = MoveDataAttackPacket {
-            role: initiator_role as u32,
-            from,
-            to,
-            bundle_null: false,
-            result: result as i32,
-            init_type: init_type as u32,
-            target_type: target_type as u32,
+    pub async fn send_auto_placement(&self, id: usize, board: &[Piece]) {
+        let packet = AutoPlacementPacket {
+            board: board
+                .iter()
+                .map(|x| (x.pig as u32, x.location as u32))
+                .collect(),
         };
-        self.message_room(room, packet).await;
+        self.message_one(id, packet).await;
     }
 
     pub async fn send_win(
         &self,
-        room: &GameRoom,
         role: PlayerRole,
         win_type: win::WinType,
         elapsed: u64,
@@ -72,12 +100,12 @@ impl GameServer {
             elapsed,
             immediate,
         };
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
-    pub async fn client_play_again(&self, room: &GameRoom, id: usize) {
+    pub async fn client_play_again(&self, id: usize) {
         let packet = ClientPlayAgainPacket { id: id.to_string() };
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
     pub async fn send_enemy_piece_data(&self, id: usize, data: Vec<(u8, u8)>) {
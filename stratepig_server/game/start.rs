@@ -1,36 +1,26 @@
 use std::collections::HashMap;
 use std::convert::TryInto;
 
+use crate::error::ErrorCode;
 use crate::packet::{GamePlayerReadyDataDefaultPacket, GamePlayerReadyDataFullPacket};
 use crate::player::{Player, PlayerRole};
-use crate::util;
-use crate::GameServer;
+use crate::win::WinType;
+use crate::GameRoom;
 use crate::StratepigError;
 use stratepig_core::{Packet, PacketBody};
-use stratepig_game::{Piece, Pig};
+use stratepig_game::{generate_setup, Piece, Pig, SetupStrategy};
 
-impl GameServer {
+impl GameRoom {
     pub async fn handle_game_player_ready(
         &mut self,
         id: usize,
         packet: Packet,
     ) -> Result<(), StratepigError> {
         let data = GamePlayerReadyDataDefaultPacket::deserialize(&packet.body)?;
-        let (_client, room) = self.get_context(id).unwrap();
-        let room_id = room.id();
-        drop(room);
 
         if !data.ready {
-            let player = self
-                .all_clients
-                .get_mut(&id)
-                .unwrap()
-                .player
-                .as_mut()
-                .unwrap();
-            player.is_ready = false;
-            let reference = self.get_room(room_id).unwrap();
-            self.game_player_ready_state(&reference, id, false).await;
+            self.player_mut(id).unwrap().is_ready = false;
+            self.game_player_ready_state(id, false).await;
 
             return Ok(());
         }
@@ -39,20 +29,27 @@ impl GameServer {
 
         let mut pig_locations = Vec::<Piece>::new();
         let mut provided_config = HashMap::new();
+        let layout = self.modes.layout(self.settings.game_mode);
 
         for (pig, location) in data.board.into_iter() {
             let pig = Pig::from(pig);
             if let Pig::Empty = pig {
-                return Err(StratepigError::with("invalid pig"));
+                return Err(StratepigError::coded(ErrorCode::BadSetup, "invalid pig"));
             }
-            if !stratepig_game::in_starting_bounds(location.try_into().unwrap_or(0)) {
-                return Err(StratepigError::with("location out of bounds"));
+            if !stratepig_game::in_starting_bounds(&layout, location.try_into().unwrap_or(0)) {
+                return Err(StratepigError::coded(
+                    ErrorCode::BadSetup,
+                    "location out of bounds",
+                ));
             }
             if pig_locations.iter().any(|x| x.location == location as u8) {
-                return Err(StratepigError::with("duplicate location placement"));
+                return Err(StratepigError::coded(
+                    ErrorCode::BadSetup,
+                    "duplicate location placement",
+                ));
             }
 
-            // Safe to cast using as, since above checks ensures location is between 1 and 40
+            // Safe to cast using as, since above checks ensures location is within the setup rows
             pig_locations.push(Piece::new(pig, location as u8));
             let value = *provided_config.get(&pig).unwrap_or(&0);
             provided_config.insert(pig, value + 1);
@@ -61,124 +58,147 @@ impl GameServer {
         // Fill in the rest with blanks
         for i in 0..13 {
             let pig = Pig::from(i);
-            if !provided_config.contains_key(&pig) {
-                provided_config.insert(pig, 0);
-            }
+            provided_config.entry(pig).or_insert(0);
         }
 
         // Ensure provided board agrees with config
-        let reference = self.get_room(room_id).unwrap();
-        let config = reference.inner().settings.pig_config.clone();
-        for (pig, amount) in config {
-            if provided_config.get(&pig).unwrap_or(&0) != &amount {
-                return Err(StratepigError::with(
+        for (pig, amount) in self.settings.pig_config.iter() {
+            if provided_config.get(pig).unwrap_or(&0) != amount {
+                return Err(StratepigError::coded(
+                    ErrorCode::BadSetup,
                     "board config does not agree with settings",
                 ));
             }
         }
-        drop(reference);
-
-        let player = self
-            .all_clients
-            .get_mut(&id)
-            .unwrap()
-            .player
-            .as_mut()
-            .unwrap();
+
+        let player = self.player_mut(id).unwrap();
         player.is_ready = true;
-        player.initialize_setup(pig_locations.clone());
-        let reference = self.get_room(room_id).unwrap();
-        self.game_player_ready_state(&reference, id, true).await;
-
-        if let Some(res) = self.get_other_player(&reference, id) {
-            drop(reference);
-            if let Some(player) = &res.player {
-                if player.is_ready {
-                    self.register_board_data(room_id).await;
-                }
-            }
-        } else if self.config.one_player {
-            let mut fake_enemy = Player::new(PlayerRole::Two);
-            fake_enemy.is_ready = true;
-            fake_enemy.initialize_setup(pig_locations);
-            reference.get().write().unwrap().fake_enemy = Some(fake_enemy);
-
-            drop(reference);
-            self.register_board_data(room_id).await;
-        }
+        player.initialize_setup(pig_locations);
+        self.game_player_ready_state(id, true).await;
 
-        Ok(())
+        self.begin_if_ready().await
     }
 
-    async fn register_board_data(&mut self, room_id: usize) {
-        let room = self.get_room(room_id).unwrap();
-
-        for id in room.inner().client_ids.iter() {
-            let locations;
-            if self.config.one_player {
-                locations = room
-                    .inner()
-                    .fake_enemy
-                    .as_ref()
-                    .unwrap()
-                    .board
-                    .iter()
-                    .map(|x| x.location)
-                    .collect();
-            } else {
-                let opp_board = &self
-                    .get_other_player(&room, id.0)
-                    .unwrap()
-                    .player
-                    .as_ref()
-                    .unwrap()
-                    .board;
-                locations = opp_board.iter().map(|x| x.location).collect();
-            }
+    /// Handles the end of the placement timer, either placing the pigs of every player
+    /// that is not ready yet or making them lose, depending on the room settings
+    pub async fn placement_expired(&mut self) -> Result<(), StratepigError> {
+        if self.game_phase != 1 || self.game_ended {
+            return Ok(());
+        }
+
+        let unready: Vec<(usize, PlayerRole)> = self
+            .members
+            .iter()
+            .filter(|x| !x.player.is_ready)
+            .map(|x| (x.id, x.player.role))
+            .collect();
+        if unready.is_empty() {
+            return Ok(());
+        }
 
-            self.opponent_pig_placement(id.0, locations).await;
+        if self.settings.forfeit_placement {
+            let role = match unready[..] {
+                [(_id, role)] => role.opp(),
+                _ => PlayerRole::Tie,
+            };
+            self.game_ended = true;
+            self.broadcast_win(role, WinType::OutOfTime).await;
+            return Ok(());
         }
 
-        self.run_operations(&room, true).await;
+        let layout = self.modes.layout(self.settings.game_mode);
+        let config = self.settings.pig_config.clone();
 
-        room.start_phase_two().await;
-        let clients = room.clients();
-        let buffer = room.inner().settings.buffer_time;
-        drop(room);
+        for (id, _role) in unready {
+            let setup = generate_setup(&layout, &config, SetupStrategy::Fortress, &mut self.rng)
+                .ok_or_else(|| StratepigError::with("pig config does not fit the board"))?;
+            self.send_auto_placement(id, &setup).await;
 
-        for id in clients {
-            let player = self.get_player_mut(id.0).unwrap();
-            player.current_buffer = buffer as u128;
+            let player = self.player_mut(id).unwrap();
+            player.is_ready = true;
+            player.initialize_setup(setup);
+            self.game_player_ready_state(id, true).await;
         }
 
-        if !(self.config.one_player || self.config.ignore_turns) {
-            self.turn_start(room_id, false).await;
+        self.begin_if_ready().await
+    }
+
+    /// Starts the game once every player in the room is ready,
+    /// placing the pigs of the computer player if there is one
+    async fn begin_if_ready(&mut self) -> Result<(), StratepigError> {
+        let vs_bot = self.settings.bot.is_some();
+
+        let expected = if vs_bot { 1 } else { 2 };
+        let ready = self.members.iter().all(|x| x.player.is_ready);
+        if self.game_phase != 1 || self.members.len() != expected || !ready {
+            return Ok(());
+        }
+
+        if vs_bot {
+            let layout = self.modes.layout(self.settings.game_mode);
+            let setup = generate_setup(
+                &layout,
+                &self.settings.pig_config,
+                SetupStrategy::Fortress,
+                &mut self.rng,
+            )
+            .ok_or_else(|| StratepigError::with("pig config does not fit the board"))?;
+
+            let mut bot = Player::new(PlayerRole::Two);
+            bot.is_ready = true;
+            bot.initialize_setup(setup);
+            self.bot = Some(bot);
         }
+
+        self.register_board_data().await;
+        Ok(())
     }
 
-    pub async fn turn_start(&mut self, room_id: usize, delay: bool) {
-        let room = self.get_room(room_id).unwrap();
+    async fn register_board_data(&mut self) {
+        // Placement is over
+        self.abort_game_ticker();
 
-        let mut write = room.get().write().unwrap();
-        if let Some(t) = &write.game_ticker {
-            t.abort();
-            write.game_ticker = None;
+        for id in self.clients() {
+            let opp_board = match self.bot.as_ref() {
+                Some(bot) => &bot.board,
+                None => &self.other_member(id).unwrap().player.board,
+            };
+            let locations = opp_board.iter().map(|x| x.location).collect();
+
+            self.opponent_pig_placement(id, locations).await;
         }
-        drop(write);
 
-        room.start_player_turn(self, delay).await;
+        self.run_operations(true).await;
+
+        self.start_phase_two().await;
+        let buffer = self.settings.buffer_time;
+        for member in self.members.iter_mut() {
+            member.player.current_buffer = buffer as u128;
+        }
+
+        if !self.config.ignore_turns {
+            self.turn_start(false).await;
+        }
+    }
+
+    pub async fn turn_start(&mut self, delay: bool) {
+        self.abort_game_ticker();
+
+        // Turns are started again once the disconnected player is back
+        if self.paused_at.is_none() {
+            self.start_player_turn(delay).await;
+        }
 
         // Set the remaining buffer time for the other player
         // (start of new turn marks end of previous turn)
-        let timestamp = room.inner().last_buffer_timestamp;
-        let other_id = room.other_id(room.get_active_id(self));
+        let timestamp = self.last_buffer_timestamp;
+        let other_id = self.get_id_by_role(self.current_turn.opp());
 
-        if let Some(timestamp) = timestamp {
-            room.get().write().unwrap().last_buffer_timestamp = None;
-            drop(room);
+        if let (Some(timestamp), Some(other_id)) = (timestamp, other_id) {
+            self.last_buffer_timestamp = None;
 
-            let diff = ((util::unix_now() - timestamp) as f32 / 1000.0).ceil() as u128;
-            let player = self.get_player_mut(other_id).unwrap();
+            let diff = ((self.clock.now_millis() - timestamp) as f32 / 1000.0).ceil() as u128;
+            let player = self.player_mut(other_id).unwrap();
             player.current_buffer -= diff;
         }
     }
@@ -1,292 +1,728 @@
 use lazy_static::lazy_static;
+use log::{info, trace, warn};
+use message_io::node::NodeHandler;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
-use std::sync::{RwLock, RwLockReadGuard};
 use std::time::Duration;
-use tokio::time;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use vec_map::VecMap;
 
-use crate::client::Client;
-use crate::packet::{RoomTimerUpdatePacket, TurnInitPacket, TurnSecondUpdatePacket, WinPacket};
-use crate::player::{Player, PlayerRole};
-use crate::util::unix_timestamp_to;
-use crate::util::{unix_now, unix_now_secs};
+use crate::clock::SharedClock;
+use crate::constants::COMPUTER_MOVE_DELAY_MS;
+use crate::error::StratepigError;
+use crate::guard::Guard;
+use crate::packet::{
+    GameTimerUpdatePacket, KickedPacket, RoomTimerUpdatePacket, TurnInitPacket,
+    TurnSecondUpdatePacket,
+};
+use crate::player::{Member, Player, PlayerRole};
 use crate::win::WinType;
-use crate::Endpoint;
-use crate::GameServer;
+use crate::ServerSignal;
 
-use crate::message_room;
+use crate::modes::{ModeDefinition, ModeRegistry};
+use stratepig_cli::CliConfig;
+use stratepig_core::{packet_name, serialize_packet, Direction, Packet, PacketBody};
+use stratepig_game::{
+    choose_move, search, Ability, Difficulty, Move, MoveHistory, Pig, RepetitionRule, RuleSet,
+    SearchConfig,
+};
 
-use stratepig_game::Pig;
+/// Commands handled one at a time by the task of a room
+pub enum RoomCommand {
+    /// A client wants to join, which fails if the room is full or playing,
+    /// unless they are the host of a new room
+    Join {
+        id: usize,
+        username: String,
+        icon: u8,
+        hosting: bool,
+        reply: oneshot::Sender<Result<(), GameRoomError>>,
+    },
+    /// A packet from a client in the room
+    Packet(usize, Packet),
+    /// The connection of a client dropped. Replies whether they are kept
+    /// to resume until `deadline`, rather than removed from the room.
+    Disconnected {
+        id: usize,
+        deadline: u128,
+        reply: oneshot::Sender<bool>,
+    },
+    /// A dropped client is back on a new connection
+    Resume(usize),
+    /// A dropped client did not resume in time
+    ReconnectExpired(usize),
+    /// Closes the room if nobody has used it for a while
+    Prune,
+    /// An event from the ticker with the given id
+    Tick(u64, Tick),
+}
 
+/// Timer events sent by the tickers of a room to its task
 #[derive(Debug)]
-pub struct GameRoomInner {
+pub enum Tick {
+    /// The lobby countdown is over
+    Countdown,
+    /// Seconds of placement time left
+    Placement(u32),
+    /// The turn of the current player begins
+    Turn,
+    /// The current player is out of turn time and into their buffer
+    Buffer,
+    /// The current player is out of buffer time
+    OutOfTime,
+    /// The computer player picked its move
+    ComputerMove(Move),
+}
+
+pub type RoomPacketHandler =
+    fn(
+        &mut GameRoom,
+        usize,
+        Packet,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StratepigError>> + Send + '_>>;
+
+/// Handlers of the packets passed on to the room of the client
+#[derive(Default)]
+pub struct RoomHandlers {
+    pub handlers: VecMap<RoomPacketHandler>,
+    pub guards: VecMap<Option<Box<dyn Guard<GameRoom>>>>,
+}
+
+impl RoomHandlers {
+    pub fn contains(&self, packet_id: u8) -> bool {
+        self.handlers.contains_key(packet_id as usize)
+    }
+}
+
+/// Where a room puts the packets for its members. The server owns their connections,
+/// so it is the one to send them.
+pub trait Outbox: Send + Sync {
+    fn send(&self, id: usize, bytes: Vec<u8>);
+}
+
+impl Outbox for NodeHandler<ServerSignal> {
+    fn send(&self, id: usize, bytes: Vec<u8>) {
+        self.signals().send(ServerSignal::Outgoing(id, bytes));
+    }
+}
+
+/// What the task of every room shares with the server
+#[derive(Clone)]
+pub struct RoomContext {
+    pub handler: NodeHandler<ServerSignal>,
+    pub outbox: Arc<dyn Outbox>,
+    pub config: Arc<CliConfig>,
+    pub modes: Arc<ModeRegistry>,
+    pub clock: SharedClock,
+    pub handlers: Arc<RoomHandlers>,
+}
+
+/// The part of a room kept by the server, to pass commands on to its task
+pub struct RoomHandle {
     pub id: usize,
     pub code: String,
-    pub client_ids: Vec<(usize, Endpoint)>,
+    sender: mpsc::UnboundedSender<RoomCommand>,
+}
+
+impl RoomHandle {
+    /// Returns false if the room has closed
+    pub fn send(&self, command: RoomCommand) -> bool {
+        self.sender.send(command).is_ok()
+    }
+}
+
+/// A task sending timer events to its room. Events can still arrive after the ticker
+/// is replaced, so they carry its id to tell them apart from those of the current one.
+struct Ticker {
+    id: u64,
+    handle: JoinHandle<()>,
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Sends the events of one ticker to its room
+struct TickSender {
+    id: u64,
+    sender: mpsc::UnboundedSender<RoomCommand>,
+}
+
+impl TickSender {
+    fn send(&self, tick: Tick) {
+        // The room may have closed meanwhile
+        let _ = self.sender.send(RoomCommand::Tick(self.id, tick));
+    }
+}
+
+pub struct GameRoom {
+    pub id: usize,
+    pub code: String,
+    pub members: Vec<Member>,
     pub in_game: bool,
     pub game_phase: u8,
     pub game_ended: bool,
     pub settings: GameRoomSettings,
-    pub fake_enemy: Option<Player>,
+    /// The computer player, once the host has placed their pigs
+    pub bot: Option<Player>,
     pub last_seen_at: u64,
+    /// Moves made this game, kept for the repetition rule
+    pub history: MoveHistory,
+    /// Source of the setups and moves of computer players, seeded by the config if set
+    pub rng: StdRng,
 
     pub current_turn: PlayerRole,
-    pub room_ticker: Option<tokio::task::JoinHandle<()>>,
-    pub game_ticker: Option<tokio::task::JoinHandle<()>>,
+    room_ticker: Option<Ticker>,
+    game_ticker: Option<Ticker>,
+    next_ticker_id: u64,
     pub last_buffer_timestamp: Option<u128>,
     pub game_start_timestamp: Option<u64>,
-}
+    /// When the running placement or turn timer runs out, kept for resuming clients
+    pub timer_deadline: Option<u128>,
+    pub timer_is_buffer: bool,
+    /// When the timers were stopped for a player that lost their connection
+    pub paused_at: Option<u128>,
+    /// Set once the room was pruned, which ends its task
+    closed: bool,
 
-type Inner = Arc<RwLock<GameRoomInner>>;
-#[derive(Debug)]
-pub struct GameRoom(Inner);
+    pub handler: NodeHandler<ServerSignal>,
+    outbox: Arc<dyn Outbox>,
+    pub config: Arc<CliConfig>,
+    pub modes: Arc<ModeRegistry>,
+    pub clock: SharedClock,
+    handlers: Arc<RoomHandlers>,
+    sender: mpsc::UnboundedSender<RoomCommand>,
+}
 
 impl GameRoom {
-    pub fn new(id: usize, code: String) -> Self {
-        Self(Arc::new(RwLock::new(GameRoomInner {
+    /// Starts the task of a new room, which owns the room from then on
+    pub fn spawn(
+        id: usize,
+        code: String,
+        settings: GameRoomSettings,
+        context: RoomContext,
+    ) -> RoomHandle {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut room = Self::new(id, code.clone(), settings, context, sender.clone());
+
+        tokio::task::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                room.handle_command(command).await;
+                if room.closed {
+                    break;
+                }
+            }
+            trace!("Room '{}' closed", room.code);
+        });
+
+        RoomHandle { id, code, sender }
+    }
+
+    /// Creates a room whose tickers send their events to `sender`
+    fn new(
+        id: usize,
+        code: String,
+        settings: GameRoomSettings,
+        context: RoomContext,
+        sender: mpsc::UnboundedSender<RoomCommand>,
+    ) -> Self {
+        Self {
             id,
             code,
-            client_ids: Vec::new(),
+            members: Vec::new(),
             in_game: false,
             game_phase: 1,
             game_ended: false,
-            settings: GameRoomSettings::new(GameMode::Original, 600, 15, 300),
-            fake_enemy: None,
-            last_seen_at: unix_now_secs(),
+            settings,
+            bot: None,
+            last_seen_at: context.clock.now_secs(),
+            history: MoveHistory::default(),
+            rng: match context.config.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
 
             current_turn: PlayerRole::One,
             room_ticker: None,
             game_ticker: None,
+            next_ticker_id: 0,
+            timer_deadline: None,
+            timer_is_buffer: false,
+            paused_at: None,
             last_buffer_timestamp: None,
             game_start_timestamp: None,
-        })))
+            closed: false,
+
+            handler: context.handler,
+            outbox: context.outbox,
+            config: context.config,
+            modes: context.modes,
+            clock: context.clock,
+            handlers: context.handlers,
+            sender,
+        }
     }
 
-    pub fn get(&self) -> &Inner {
-        &self.0
+    async fn handle_command(&mut self, command: RoomCommand) {
+        match command {
+            RoomCommand::Join {
+                id,
+                username,
+                icon,
+                hosting,
+                reply,
+            } => {
+                let res = self.join(id, username, icon, hosting).await;
+                // The server only stops waiting if it is shutting down
+                let _ = reply.send(res);
+            }
+            RoomCommand::Packet(id, packet) => self.handle_packet(id, packet).await,
+            RoomCommand::Disconnected {
+                id,
+                deadline,
+                reply,
+            } => {
+                let kept = self.disconnected(id, deadline).await;
+                let _ = reply.send(kept);
+            }
+            RoomCommand::Resume(id) => self.resume(id).await,
+            RoomCommand::ReconnectExpired(id) => {
+                self.forfeit_disconnected(id).await;
+                self.remove_member(id).await;
+            }
+            RoomCommand::Prune => self.prune().await,
+            RoomCommand::Tick(ticker, tick) => self.handle_tick(ticker, tick).await,
+        }
     }
 
-    pub fn inner(&self) -> RwLockReadGuard<GameRoomInner> {
-        self.0.read().unwrap()
+    async fn handle_packet(&mut self, id: usize, packet: Packet) {
+        let handlers = self.handlers.clone();
+        let packet_id = packet.header.id as usize;
+        let func = match handlers.handlers.get(packet_id) {
+            Some(func) => func,
+            None => return,
+        };
+
+        // Evaluate guards
+        if let Some(guard) = handlers.guards.get(packet_id).unwrap() {
+            if self.config.log_packet_output {
+                info!("Checking guard '{}'", guard.name());
+            }
+
+            if let Err(err) = guard.guard(id, packet.clone(), self) {
+                warn!("Guard failed: {:?}", err);
+                self.send_error(id, packet.header.id, &err).await;
+                return;
+            }
+        }
+
+        let res = func(self, id, packet.clone()).await;
+        if self.config.log_packet_output {
+            info!(
+                "Client {}: {} ==> {:?}",
+                id,
+                packet_name(Direction::Client, packet.header.id).unwrap_or("Unknown"),
+                res
+            );
+        }
+        if let Err(err) = &res {
+            self.send_error(id, packet.header.id, err).await;
+        }
+    }
+
+    async fn handle_tick(&mut self, ticker: u64, tick: Tick) {
+        let current = match tick {
+            Tick::Countdown => &self.room_ticker,
+            _ => &self.game_ticker,
+        };
+        if current.as_ref().map(|x| x.id) != Some(ticker) {
+            return;
+        }
+
+        match tick {
+            Tick::Countdown => self.in_game = true,
+            Tick::Placement(remaining) => {
+                let packet = GameTimerUpdatePacket {
+                    timestamp: self.timer_deadline.unwrap_or(0),
+                    server_now: self.clock.now_millis(),
+                    remaining,
+                };
+                self.message_room(packet).await;
+
+                if remaining == 0 {
+                    if let Err(err) = self.placement_expired().await {
+                        warn!("Placement expiry failed: {:?}", err);
+                    }
+                }
+            }
+            Tick::Turn => self.turn_began().await,
+            Tick::Buffer => self.buffer_began().await,
+            Tick::OutOfTime => self.out_of_time().await,
+            Tick::ComputerMove(mv) => {
+                if let Err(err) = self.computer_move(mv).await {
+                    warn!("Computer turn failed: {:?}", err);
+                }
+            }
+        }
     }
 
-    pub fn id(&self) -> usize {
-        self.0.read().unwrap().id
+    pub async fn message_one(&self, id: usize, packet: impl PacketBody) {
+        if self.config.log_packet_output {
+            info!(
+                "OUTBOUND({}) => {}",
+                id,
+                packet_name(Direction::Server, packet.id()).unwrap_or("Unknown")
+            );
+        }
+        if self.member(id).is_some_and(|x| !x.dropped) {
+            self.outbox
+                .send(id, serialize_packet(Box::new(packet)).unwrap());
+        }
     }
 
-    pub fn clients(&self) -> Vec<(usize, Endpoint)> {
-        self.0.read().unwrap().client_ids.clone()
+    pub async fn message_room(&self, packet: impl PacketBody) {
+        if self.config.log_packet_output {
+            info!(
+                "OUTBOUND({:?}) => {}",
+                self.clients(),
+                packet_name(Direction::Server, packet.id()).unwrap_or("Unknown")
+            );
+        }
+
+        let bytes = serialize_packet(Box::new(packet)).unwrap();
+        for member in self.members.iter().filter(|x| !x.dropped) {
+            self.outbox.send(member.id, bytes.clone());
+        }
     }
 
-    pub fn load_default_settings(&self) {
-        self.0.write().unwrap().settings = GameRoomSettings::default();
+    pub fn clients(&self) -> Vec<usize> {
+        self.members.iter().map(|x| x.id).collect()
     }
 
-    pub fn other_id(&self, id: usize) -> usize {
-        let clients = self.clients();
-        let other: Vec<usize> = clients.iter().map(|x| x.0).filter(|x| *x != id).collect();
-        other[0]
+    pub fn member(&self, id: usize) -> Option<&Member> {
+        self.members.iter().find(|x| x.id == id)
     }
 
-    pub fn get_active_id(&self, game: &GameServer) -> usize {
-        let role = self.inner().current_turn;
-        for (id, _endpoint) in self.clients().iter() {
-            let player = game.get_player(*id).unwrap();
-            if player.role == role {
-                return *id;
-            }
-        }
-        panic!("Client options exhausted!");
+    pub fn member_mut(&mut self, id: usize) -> Option<&mut Member> {
+        self.members.iter_mut().find(|x| x.id == id)
     }
 
-    pub fn store_seen(&self) {
-        self.get().write().unwrap().last_seen_at = unix_now_secs();
+    pub fn other_member(&self, id: usize) -> Option<&Member> {
+        self.members.iter().find(|x| x.id != id)
     }
 
-    pub async fn start(&self, game: &GameServer, in_secs: u64) {
-        let inner = self.get().clone();
-        let duration = Duration::from_secs(in_secs);
-        let timestamp = unix_timestamp_to(duration);
+    pub fn player(&self, id: usize) -> Option<&Player> {
+        self.member(id).map(|x| &x.player)
+    }
 
-        let packet = RoomTimerUpdatePacket {
-            timestamp: timestamp as i128,
-            server_now: unix_now(),
-        };
-        game.message_room(self, packet).await;
+    pub fn player_mut(&mut self, id: usize) -> Option<&mut Player> {
+        self.member_mut(id).map(|x| &mut x.player)
+    }
 
-        let handle = tokio::task::spawn(async move {
-            time::sleep(duration).await;
-            inner.write().unwrap().in_game = true;
-        });
-        self.get().write().unwrap().room_ticker = Some(handle);
+    pub fn get_active_id(&self) -> Option<usize> {
+        self.get_id_by_role(self.current_turn)
     }
 
-    pub fn cancel_start(&self) {
-        let mut write = self.get().write().unwrap();
-        if let Some(t) = &write.room_ticker {
-            t.abort();
-            write.room_ticker = None;
-        }
+    /// Finds the client playing as `role`, which is `None` for the computer player
+    pub fn get_id_by_role(&self, role: PlayerRole) -> Option<usize> {
+        self.members
+            .iter()
+            .find(|x| x.player.role == role)
+            .map(|x| x.id)
     }
 
-    pub async fn start_phase_two(&self) {
-        let mut write = self.get().write().unwrap();
-        write.game_phase = 2;
-        write.game_start_timestamp = Some(unix_now_secs());
+    pub fn is_bot_turn(&self) -> bool {
+        self.bot.as_ref().map(|x| x.role) == Some(self.current_turn)
     }
 
-    pub async fn start_player_turn(&self, game: &GameServer, delay: bool) {
-        let role = self.inner().current_turn;
+    pub fn store_seen(&mut self) {
+        self.last_seen_at = self.clock.now_secs();
+    }
 
-        let inner = self.get().clone();
-        let handler = game.handler.clone();
+    pub fn generate_safe_username(&self, username: &str) -> String {
+        let taken = |name: &str| self.members.iter().any(|x| x.room_player.username == name);
 
-        let player = game.get_player(self.get_active_id(game));
-        if player.is_none() {
-            return;
+        let mut final_username = String::from(username);
+        let mut i = 1;
+        while taken(&final_username) {
+            final_username = format!("{} {}", username, i);
+            i += 1;
         }
-        let player_buffer = player.unwrap().current_buffer;
-        let mut write = self.get().write().unwrap();
+        final_username
+    }
 
-        let handle = tokio::task::spawn(async move {
-            if delay {
-                time::sleep(Duration::from_secs(4)).await;
-            }
+    /// Removes a client that left or lost their connection, making whoever is left the host.
+    /// Nothing is functional with only one player, so the tickers are stopped.
+    pub async fn remove_member(&mut self, id: usize) {
+        let index = match self.members.iter().position(|x| x.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+        let member = self.members.remove(index);
+        self.in_game = false;
+        self.abort_all_tickers();
 
-            let packet = TurnInitPacket { role: role as u32 };
-            {
-                // For some weird reason this is required to be in a separate scope
-                message_room!(handler, inner, packet);
+        if !self.members.is_empty() {
+            self.client_disconnected(id).await;
+        }
+        if member.player.role == PlayerRole::One {
+            if let Some(stay) = self.members.first_mut() {
+                stay.player.role = PlayerRole::One;
             }
+        }
 
-            let turn_duration =
-                Duration::from_secs(inner.read().unwrap().settings.turn_time as u64);
-            let turn_timestamp = unix_timestamp_to(turn_duration);
+        self.handler
+            .signals()
+            .send(ServerSignal::LeftRoom(id, self.id));
+    }
 
-            let packet = TurnSecondUpdatePacket {
-                role: role as u32,
-                turn_timestamp,
-                server_now: unix_now(),
-                is_buffer: false,
-            };
-            {
-                message_room!(handler, inner, packet);
-            }
+    /// Kicks everyone and ends the task of the room if it has not been used for a while
+    async fn prune(&mut self) {
+        let stale = self.clock.now_secs() > self.last_seen_at + self.config.max_prune_age_secs;
+        if (self.in_game && !self.game_ended) || !stale {
+            return;
+        }
 
-            time::sleep(turn_duration).await;
+        let packet = KickedPacket {
+            msg: "Room closed due to inactivity.".to_owned(),
+        };
+        self.message_room(packet).await;
 
-            let buffer_duration = Duration::from_secs(player_buffer as u64);
-            let buffer_timestamp = unix_timestamp_to(buffer_duration);
+        self.closed = true;
+        self.abort_all_tickers();
+        self.handler
+            .signals()
+            .send(ServerSignal::RoomClosed(self.id));
+    }
 
-            let packet = TurnSecondUpdatePacket {
-                role: role as u32,
-                turn_timestamp: buffer_timestamp,
-                server_now: unix_now(),
-                is_buffer: true,
-            };
-            {
-                message_room!(handler, inner, packet);
-            }
+    fn spawn_ticker<F>(&mut self, task: impl FnOnce(TickSender) -> F) -> Ticker
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.next_ticker_id += 1;
+        let tick = TickSender {
+            id: self.next_ticker_id,
+            sender: self.sender.clone(),
+        };
+        Ticker {
+            id: self.next_ticker_id,
+            handle: tokio::task::spawn(task(tick)),
+        }
+    }
 
-            inner.write().unwrap().last_buffer_timestamp = Some(unix_now());
+    pub async fn start(&mut self, in_secs: u64) {
+        let duration = Duration::from_secs(in_secs);
+        let timestamp = self.clock.millis_in(duration);
 
-            time::sleep(buffer_duration).await;
+        let packet = RoomTimerUpdatePacket {
+            timestamp: timestamp as i128,
+            server_now: self.clock.now_millis(),
+        };
+        self.message_room(packet).await;
 
-            inner.write().unwrap().game_ended = true;
+        let clock = self.clock.clone();
+        let ticker = self.spawn_ticker(move |tick| async move {
+            clock.sleep(duration).await;
+            tick.send(Tick::Countdown);
+        });
+        self.room_ticker = Some(ticker);
+    }
 
-            {
-                let read = inner.read().unwrap();
-                let start = read.game_start_timestamp.unwrap_or(unix_now_secs());
-                let elapsed = unix_now_secs() - start;
+    pub fn cancel_start(&mut self) {
+        self.room_ticker = None;
+    }
 
-                let packet = WinPacket {
-                    role: read.current_turn.opp() as u32,
-                    win_type: WinType::OutOfTime as u32,
-                    elapsed,
-                    immediate: WinType::OutOfTime.immediate(),
-                };
+    /// Counts down `placement_time` seconds, after which
+    /// any player that is not ready is dealt with
+    pub async fn start_placement(&mut self, placement_time: u32) {
+        let timestamp = self
+            .clock
+            .millis_in(Duration::from_secs(placement_time as u64));
 
-                drop(read);
+        let clock = self.clock.clone();
+        let ticker = self.spawn_ticker(move |tick| async move {
+            for remaining in (0..=placement_time).rev() {
+                tick.send(Tick::Placement(remaining));
 
-                message_room!(handler, inner, packet);
+                if remaining > 0 {
+                    clock.sleep(Duration::from_secs(1)).await;
+                }
             }
         });
-        write.game_ticker = Some(handle);
-    }
-
-    pub fn reset(&self) {
-        let mut write = self.get().write().unwrap();
-
-        write.current_turn = PlayerRole::One;
-        write.game_phase = 1;
-        write.in_game = false;
-        write.game_ended = false;
 
-        write.last_buffer_timestamp = None;
-        write.game_start_timestamp = None;
+        self.game_ticker = Some(ticker);
+        self.timer_deadline = Some(timestamp);
+        self.timer_is_buffer = false;
+    }
 
-        write.abort_all_tickers();
+    pub fn abort_game_ticker(&mut self) {
+        self.game_ticker = None;
     }
-}
 
-impl GameRoomInner {
     pub fn abort_all_tickers(&mut self) {
-        if let Some(t) = &self.room_ticker {
-            t.abort();
-            self.room_ticker = None;
-        }
-        if let Some(t) = &self.game_ticker {
-            t.abort();
-            self.game_ticker = None;
-        }
+        self.room_ticker = None;
+        self.game_ticker = None;
     }
-}
 
-impl Drop for GameRoomInner {
-    fn drop(&mut self) {
-        self.client_ids.clear();
-        self.abort_all_tickers();
+    pub async fn start_phase_two(&mut self) {
+        self.game_phase = 2;
+        self.game_start_timestamp = Some(self.clock.now_secs());
     }
-}
 
-impl GameServer {
-    pub fn get_client_by_name(&self, room: &GameRoom, username: &str) -> Option<&Client> {
-        for (id, _endpoint) in room.clients() {
-            let client = self.all_clients.get(&id).unwrap();
-            if let None = client.room_player {
-                continue;
+    pub async fn start_player_turn(&mut self, delay: bool) {
+        let role = self.current_turn;
+        let clock = self.clock.clone();
+        let attack_delay = Duration::from_secs(self.config.attack_delay_secs);
+
+        if self.is_bot_turn() {
+            self.timer_deadline = None;
+            let state = self.game_state();
+            let difficulty = self.settings.bot.unwrap();
+            let mut search_config = SearchConfig {
+                time_budget: Duration::from_millis(self.config.think_time_ms),
+                ..SearchConfig::default()
+            };
+            if let Some(max) = self.config.max_iterations {
+                search_config.max_iterations = Some(max);
             }
-            if client.room_player.as_ref().unwrap().username.eq(username) {
-                return Some(client);
+            // A seeded game is replayed move for move, which a time budget would break
+            if self.config.seed.is_some() {
+                search_config.time_budget = Duration::MAX;
             }
+            // Each move gets its own generator, as the search runs on another thread
+            let seed = self.rng.gen();
+
+            let ticker = self.spawn_ticker(move |tick| async move {
+                if delay {
+                    clock.sleep(attack_delay).await;
+                }
+                tick.send(Tick::Turn);
+
+                // Searching can take a while, so it is kept off the async threads
+                let thinking = tokio::task::spawn_blocking(move || {
+                    let state = state?;
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    match difficulty {
+                        Difficulty::Expert => search(&state, role, &search_config, &mut rng),
+                        _ => choose_move(&state, role, difficulty, &mut rng),
+                    }
+                });
+                clock
+                    .sleep(Duration::from_millis(COMPUTER_MOVE_DELAY_MS))
+                    .await;
+
+                // Running out of moves is caught by the operations after the previous move
+                if let Ok(Some(mv)) = thinking.await {
+                    tick.send(Tick::ComputerMove(mv));
+                }
+            });
+            self.game_ticker = Some(ticker);
+            return;
         }
-        None
-    }
 
-    pub fn generate_safe_username(&self, room: &GameRoom, username: &str) -> String {
-        let mut final_username = String::from(username);
-        if let Some(_) = self.get_client_by_name(room, &final_username) {
-            let mut i = 1;
-            while let Some(_) = self.get_client_by_name(room, &final_username) {
-                final_username = format!("{} {}", username, i);
-                i += 1;
+        let player_buffer = match self.get_active_id().and_then(|id| self.player(id)) {
+            Some(player) => player.current_buffer,
+            None => return,
+        };
+        let turn_duration = Duration::from_secs(self.settings.turn_time as u64);
+        let buffer_duration = Duration::from_secs(player_buffer as u64);
+
+        let ticker = self.spawn_ticker(move |tick| async move {
+            if delay {
+                clock.sleep(attack_delay).await;
             }
-        }
-        final_username
+
+            tick.send(Tick::Turn);
+            clock.sleep(turn_duration).await;
+            tick.send(Tick::Buffer);
+            clock.sleep(buffer_duration).await;
+            tick.send(Tick::OutOfTime);
+        });
+        self.game_ticker = Some(ticker);
     }
 
-    pub fn get_other_player(&self, room: &GameRoom, id: usize) -> Option<&Client> {
-        let result: Vec<usize> = room
-            .clients()
-            .iter()
-            .map(|x| x.0)
-            .filter(|x| *x != id)
-            .collect();
-        if result.len() == 0 {
-            return None;
-        } else {
-            return Some(self.all_clients.get(&result[0]).unwrap());
+    /// Tells everyone whose turn it is, along with the turn timer of a player
+    async fn turn_began(&mut self) {
+        let role = self.current_turn;
+        let packet = TurnInitPacket { role: role as u32 };
+        self.message_room(packet).await;
+
+        if self.is_bot_turn() {
+            return;
         }
+
+        let turn_duration = Duration::from_secs(self.settings.turn_time as u64);
+        let turn_timestamp = self.clock.millis_in(turn_duration);
+        self.timer_deadline = Some(turn_timestamp);
+        self.timer_is_buffer = false;
+
+        let packet = TurnSecondUpdatePacket {
+            role: role as u32,
+            turn_timestamp,
+            server_now: self.clock.now_millis(),
+            is_buffer: false,
+        };
+        self.message_room(packet).await;
+    }
+
+    async fn buffer_began(&mut self) {
+        let player_buffer = self
+            .get_active_id()
+            .and_then(|id| self.player(id))
+            .map_or(0, |x| x.current_buffer);
+        let buffer_timestamp = self
+            .clock
+            .millis_in(Duration::from_secs(player_buffer as u64));
+        self.timer_deadline = Some(buffer_timestamp);
+        self.timer_is_buffer = true;
+
+        let packet = TurnSecondUpdatePacket {
+            role: self.current_turn as u32,
+            turn_timestamp: buffer_timestamp,
+            server_now: self.clock.now_millis(),
+            is_buffer: true,
+        };
+        self.message_room(packet).await;
+
+        self.last_buffer_timestamp = Some(self.clock.now_millis());
+    }
+
+    async fn out_of_time(&mut self) {
+        self.game_ended = true;
+
+        let now = self.clock.now_secs();
+        let elapsed = now - self.game_start_timestamp.unwrap_or(now);
+        self.send_win(
+            self.current_turn.opp(),
+            WinType::OutOfTime,
+            elapsed,
+            WinType::OutOfTime.immediate(),
+        )
+        .await;
+    }
+
+    pub fn reset(&mut self) {
+        self.current_turn = PlayerRole::One;
+        self.game_phase = 1;
+        self.in_game = false;
+        self.game_ended = false;
+        self.history = MoveHistory::default();
+        self.bot = None;
+
+        self.last_buffer_timestamp = None;
+        self.game_start_timestamp = None;
+        self.timer_deadline = None;
+        self.timer_is_buffer = false;
+        self.paused_at = None;
+
+        self.abort_all_tickers();
     }
 }
 
@@ -296,18 +732,65 @@ pub struct GameRoomSettings {
     pub placement_time: u32,
     pub turn_time: u32,
     pub buffer_time: u32,
+    pub rules: RuleSet,
+    /// Difficulty of the computer opponent, if the host is playing against one
+    pub bot: Option<Difficulty>,
+    /// Whether running out of placement time loses the game,
+    /// rather than placing the remaining pigs automatically
+    pub forfeit_placement: bool,
 
     pub pig_config: HashMap<Pig, u8>,
 }
 
 impl GameRoomSettings {
-    pub fn new(game_mode: GameMode, placement_time: u32, turn_time: u32, buffer_time: u32) -> Self {
+    pub fn from_mode(def: &ModeDefinition) -> Self {
         Self {
-            game_mode,
-            placement_time,
-            turn_time,
-            buffer_time,
-            pig_config: HashMap::new(),
+            game_mode: def.mode,
+            placement_time: def.placement_time,
+            turn_time: def.turn_time,
+            buffer_time: def.buffer_time,
+            rules: def.rules,
+            bot: None,
+            forfeit_placement: false,
+            pig_config: def.pig_config.clone(),
+        }
+    }
+
+    /// Gets the value of a lobby setting, where house rules are sent as 0 or 1
+    pub fn value(&self, settings_id: u32) -> Option<u32> {
+        let value = match settings_id {
+            0 => self.game_mode.0 as u32,
+            1 => self.placement_time,
+            2 => self.turn_time,
+            3 => self.buffer_time,
+            4 => self.rules.chemist.map_or(0, |x| x as u32),
+            5 => self.rules.scout_move_and_strike as u32,
+            6 => self.rules.single_use_bombs as u32,
+            7 => self.rules.attacker_wins_ties as u32,
+            8 => self.rules.spy_attack_only as u32,
+            9 => self.rules.repetition as u32,
+            10 => self.bot.map_or(0, |x| x as u32),
+            11 => self.forfeit_placement as u32,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub fn set_value(&mut self, settings_id: u32, value: u32) {
+        match settings_id {
+            0 => self.game_mode = GameMode(value as u8),
+            1 => self.placement_time = value,
+            2 => self.turn_time = value,
+            3 => self.buffer_time = value,
+            4 => self.rules.chemist = Ability::from(value),
+            5 => self.rules.scout_move_and_strike = value != 0,
+            6 => self.rules.single_use_bombs = value != 0,
+            7 => self.rules.attacker_wins_ties = value != 0,
+            8 => self.rules.spy_attack_only = value != 0,
+            9 => self.rules.repetition = RepetitionRule::from(value),
+            10 => self.bot = Difficulty::from(value),
+            11 => self.forfeit_placement = value != 0,
+            _ => {}
         }
     }
 
@@ -324,42 +807,28 @@ impl GameRoomSettings {
             placement_time,
             turn_time,
             buffer_time,
+            rules: RuleSet::default(),
+            bot: None,
+            forfeit_placement: false,
             pig_config,
         }
     }
-
-    pub fn default() -> Self {
-        Self {
-            game_mode: GameMode::Original,
-            placement_time: 300,
-            turn_time: 15,
-            buffer_time: 300,
-            pig_config: get_pig_config_for_mode(GameMode::Original).unwrap(),
-        }
-    }
 }
 
+/// Id of a mode loaded into the `ModeRegistry`, or the custom mode
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-pub enum GameMode {
-    Original = 1,
-    Infiltrator = 2,
-    Duel = 3,
-    Custom = 4,
-}
+pub struct GameMode(pub u8);
 
 impl GameMode {
-    pub const MAX: u8 = 4;
-
-    pub fn from(val: u8) -> Self {
-        match val {
-            1 => Self::Original,
-            2 => Self::Infiltrator,
-            3 => Self::Duel,
-            _ => Self::Custom,
-        }
-    }
+    /// Rooms with pig counts changed by hand
+    pub const CUSTOM: Self = Self(4);
 }
 
+/// Highest settings id the host can change in the lobby
+pub const MAX_SETTINGS_ID: u32 = 11;
+/// Setting for the computer opponent, which can only be changed without a second player
+pub const BOT_SETTINGS_ID: u32 = 10;
+
 pub struct SettingsGroup {
     pub loopable: bool,
     pub min_val: i32,
@@ -401,92 +870,347 @@ lazy_static! {
                 default: 300,
             },
         );
+        map.insert(
+            4,
+            SettingsGroup {
+                loopable: true,
+                min_val: 0,
+                max_val: Ability::MAX as i32,
+                interval: 1,
+                // Abilities change the classic rules, so rooms opt in to them
+                default: 0,
+            },
+        );
+        // House rules toggled between 0 and 1
+        for (id, default) in [(5, true), (6, false), (7, false), (8, true)].iter() {
+            map.insert(
+                *id,
+                SettingsGroup {
+                    loopable: true,
+                    min_val: 0,
+                    max_val: 1,
+                    interval: 1,
+                    default: *default as i32,
+                },
+            );
+        }
+        map.insert(
+            9,
+            SettingsGroup {
+                loopable: true,
+                min_val: 0,
+                max_val: RepetitionRule::MAX as i32,
+                interval: 1,
+                default: RepetitionRule::TwoSquare as i32,
+            },
+        );
+        map.insert(
+            10,
+            SettingsGroup {
+                loopable: true,
+                min_val: 0,
+                max_val: Difficulty::MAX as i32,
+                interval: 1,
+                default: 0,
+            },
+        );
+        map.insert(
+            11,
+            SettingsGroup {
+                loopable: true,
+                min_val: 0,
+                max_val: 1,
+                interval: 1,
+                default: 0,
+            },
+        );
         map
     };
 }
 
-pub fn get_pig_config_for_mode(mode: GameMode) -> Option<HashMap<Pig, u8>> {
-    let mut map = HashMap::new();
-    match mode {
-        GameMode::Original => {
-            map.insert(Pig::Bomb, 6);
-            map.insert(Pig::Spy, 1);
-            map.insert(Pig::Infiltrator, 0);
-            map.insert(Pig::Flag, 1);
-            map.insert(Pig::Scout, 8);
-            map.insert(Pig::Miner, 5);
-            map.insert(Pig::Sergeant, 4);
-            map.insert(Pig::Lieutenant, 4);
-            map.insert(Pig::Chemist, 4);
-            map.insert(Pig::Major, 3);
-            map.insert(Pig::Colonel, 2);
-            map.insert(Pig::General, 1);
-            map.insert(Pig::Kingo, 1);
-            Some(map)
+#[derive(Debug)]
+pub enum GameRoomError {
+    NotFound,
+    Started,
+    Full,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, ManualClock};
+    use crate::packet::{AutoPlacementPacket, ClientDisconnectPacket, WinPacket};
+    use crate::player::RoomPlayer;
+    use message_io::node::{self, NodeListener};
+    use std::sync::Mutex;
+    use stratepig_game::{generate_setup, Board, SetupStrategy};
+
+    /// Keeps the packets a room sends, in place of the connections of its members
+    #[derive(Default)]
+    struct TestOutbox {
+        sent: Mutex<Vec<(usize, Vec<u8>)>>,
+    }
+
+    impl Outbox for TestOutbox {
+        fn send(&self, id: usize, bytes: Vec<u8>) {
+            self.sent.lock().unwrap().push((id, bytes));
         }
-        GameMode::Infiltrator => {
-            map.insert(Pig::Bomb, 6);
-            map.insert(Pig::Spy, 1);
-            map.insert(Pig::Infiltrator, 1);
-            map.insert(Pig::Flag, 1);
-            map.insert(Pig::Scout, 7);
-            map.insert(Pig::Miner, 5);
-            map.insert(Pig::Sergeant, 4);
-            map.insert(Pig::Lieutenant, 4);
-            map.insert(Pig::Chemist, 4);
-            map.insert(Pig::Major, 3);
-            map.insert(Pig::Colonel, 2);
-            map.insert(Pig::General, 1);
-            map.insert(Pig::Kingo, 1);
-            Some(map)
+    }
+
+    /// A room fed by hand, with members 1 and 2 playing as player one and two
+    struct TestRoom {
+        room: GameRoom,
+        commands: mpsc::UnboundedReceiver<RoomCommand>,
+        clock: Arc<ManualClock>,
+        outbox: Arc<TestOutbox>,
+        /// Holds the signals the room sends to the server
+        _listener: NodeListener<ServerSignal>,
+    }
+
+    impl TestRoom {
+        fn new(settings: GameRoomSettings) -> Self {
+            Self::with_config(settings, CliConfig::default())
         }
-        GameMode::Duel => {
-            map.insert(Pig::Bomb, 2);
-            map.insert(Pig::Spy, 1);
-            map.insert(Pig::Infiltrator, 0);
-            map.insert(Pig::Flag, 1);
-            map.insert(Pig::Scout, 2);
-            map.insert(Pig::Miner, 2);
-            map.insert(Pig::Sergeant, 0);
-            map.insert(Pig::Lieutenant, 0);
-            map.insert(Pig::Chemist, 0);
-            map.insert(Pig::Major, 0);
-            map.insert(Pig::Colonel, 0);
-            map.insert(Pig::General, 1);
-            map.insert(Pig::Kingo, 1);
-            Some(map)
+
+        fn with_config(settings: GameRoomSettings, config: CliConfig) -> Self {
+            let (handler, listener) = node::split::<ServerSignal>();
+            let clock = Arc::new(ManualClock::new(Duration::from_secs(1_000_000)));
+            let outbox = Arc::new(TestOutbox::default());
+            let context = RoomContext {
+                handler,
+                outbox: outbox.clone(),
+                config: Arc::new(config),
+                modes: Arc::new(ModeRegistry::builtin().unwrap()),
+                clock: clock.clone(),
+                handlers: Arc::new(RoomHandlers::default()),
+            };
+            let (sender, commands) = mpsc::unbounded_channel();
+            let mut room = GameRoom::new(1, "TEST".to_owned(), settings, context, sender);
+
+            for (id, role) in [(1, PlayerRole::One), (2, PlayerRole::Two)] {
+                let room_player = RoomPlayer::new(format!("player {}", id), 0);
+                room.members.push(Member::new(id, role, room_player));
+            }
+
+            Self {
+                room,
+                commands,
+                clock,
+                outbox,
+                _listener: listener,
+            }
+        }
+
+        /// Hands the room the next event of its tickers
+        async fn tick(&mut self) {
+            let command = self.commands.recv().await.unwrap();
+            self.room.handle_command(command).await;
+        }
+
+        /// Takes the first packet with the given id sent to a member,
+        /// along with the packets sent to them before it
+        fn expect<P: PacketBody>(&self, member: usize, id: u8) -> P {
+            let mut sent = self.outbox.sent.lock().unwrap();
+            let index = sent
+                .iter()
+                .position(|(to, bytes)| *to == member && bytes[2] == id)
+                .unwrap_or_else(|| panic!("packet {} was not sent to {}", id, member));
+
+            let rest = sent.split_off(index + 1);
+            let (_to, bytes) = sent.pop().unwrap();
+            sent.retain(|(to, _bytes)| *to != member);
+            sent.extend(rest);
+            P::deserialize(&bytes[3..]).unwrap()
+        }
+
+        /// Whether a packet with the given id is waiting for a member
+        fn was_sent(&self, member: usize, id: u8) -> bool {
+            let sent = self.outbox.sent.lock().unwrap();
+            sent.iter()
+                .any(|(to, bytes)| *to == member && bytes[2] == id)
         }
-        _ => None,
     }
-}
 
-pub fn get_settings_vars(mode: GameMode) -> SettingsVars {
-    if mode == GameMode::Duel {
-        return SettingsVars {
-            buffer_time: 180,
-            ..Default::default()
-        };
+    /// A room in placement where only player one has placed their pigs
+    fn placing(forfeit_placement: bool) -> TestRoom {
+        let modes = ModeRegistry::builtin().unwrap();
+        let mut settings = GameRoomSettings::from_mode(modes.default_mode());
+        settings.forfeit_placement = forfeit_placement;
+        let layout = modes.layout(settings.game_mode);
+        let setup = generate_setup(
+            &layout,
+            &settings.pig_config,
+            SetupStrategy::Random,
+            &mut rand::thread_rng(),
+        )
+        .unwrap();
+
+        let mut test = TestRoom::new(settings);
+        test.room.in_game = true;
+        let player = &mut test.room.members[0].player;
+        player.is_ready = true;
+        player.initialize_setup(setup);
+        test
     }
-    return Default::default();
-}
 
-pub struct SettingsVars {
-    pub turn_time: u32,
-    pub buffer_time: u32,
-}
+    /// Runs a placement timer of `secs` seconds to its end
+    async fn run_placement(test: &mut TestRoom, secs: u32) {
+        test.room.start_placement(secs).await;
+        for remaining in (0..=secs).rev() {
+            test.tick().await;
+            let update: GameTimerUpdatePacket = test.expect(1, GameTimerUpdatePacket::ID);
+            assert_eq!(update.remaining, remaining);
+            test.clock.advance(Duration::from_secs(1));
+        }
+    }
 
-impl std::default::Default for SettingsVars {
-    fn default() -> Self {
-        Self {
-            turn_time: 15,
-            buffer_time: 300,
+    #[tokio::test]
+    async fn placement_expiry_places_pigs() {
+        let mut test = placing(false);
+        run_placement(&mut test, 3).await;
+
+        let placed: AutoPlacementPacket = test.expect(2, AutoPlacementPacket::ID);
+        let pigs: u8 = test.room.settings.pig_config.values().sum();
+        assert_eq!(placed.board.len(), pigs as usize);
+        assert!(!test.was_sent(1, AutoPlacementPacket::ID));
+
+        let player = &test.room.members[1].player;
+        assert!(player.is_ready);
+        assert_eq!(player.board.len(), pigs as usize);
+        assert_eq!(test.room.game_phase, 2);
+        assert!(!test.room.game_ended);
+    }
+
+    #[tokio::test]
+    async fn placement_expiry_forfeits() {
+        let mut test = placing(true);
+        run_placement(&mut test, 3).await;
+
+        let win: WinPacket = test.expect(2, WinPacket::ID);
+        assert_eq!(win.role, PlayerRole::One as u32);
+        assert_eq!(win.win_type, WinType::OutOfTime as u32);
+        assert!(!test.was_sent(2, AutoPlacementPacket::ID));
+        assert_eq!(test.room.game_phase, 1);
+        assert!(test.room.game_ended);
+    }
+
+    #[tokio::test]
+    async fn turn_runs_out_of_time() {
+        let modes = ModeRegistry::builtin().unwrap();
+        let mut settings = GameRoomSettings::from_mode(modes.default_mode());
+        settings.turn_time = 30;
+        settings.buffer_time = 60;
+        let mut test = TestRoom::new(settings);
+
+        test.room.in_game = true;
+        test.room.start_phase_two().await;
+        for member in test.room.members.iter_mut() {
+            member.player.current_buffer = 60;
         }
+        test.room.start_player_turn(false).await;
+
+        test.tick().await;
+        let turn: TurnSecondUpdatePacket = test.expect(2, TurnSecondUpdatePacket::ID);
+        assert_eq!(turn.role, PlayerRole::One as u32);
+        assert!(!turn.is_buffer);
+        assert_eq!(
+            turn.turn_timestamp,
+            test.clock.millis_in(Duration::from_secs(30))
+        );
+
+        test.clock.advance(Duration::from_secs(30));
+        test.tick().await;
+        let buffer: TurnSecondUpdatePacket = test.expect(2, TurnSecondUpdatePacket::ID);
+        assert!(buffer.is_buffer);
+        assert_eq!(
+            buffer.turn_timestamp,
+            test.clock.millis_in(Duration::from_secs(60))
+        );
+
+        test.clock.advance(Duration::from_secs(60));
+        test.tick().await;
+        let win: WinPacket = test.expect(1, WinPacket::ID);
+        assert_eq!(win.role, PlayerRole::Two as u32);
+        assert_eq!(win.win_type, WinType::OutOfTime as u32);
+        assert_eq!(win.elapsed, 90);
+        assert!(test.room.game_ended);
     }
-}
 
-#[derive(Debug)]
-pub enum GameRoomError {
-    NotFound,
-    Started,
-    Full,
+    #[tokio::test]
+    async fn dropped_player_loses_after_deadline() {
+        let modes = ModeRegistry::builtin().unwrap();
+        let mut test = TestRoom::new(GameRoomSettings::from_mode(modes.default_mode()));
+        test.room.in_game = true;
+        test.room.start_phase_two().await;
+        test.room.start_player_turn(false).await;
+        test.tick().await;
+
+        // The server makes the room forfeit the player once their grace period is over
+        let grace = Duration::from_secs(test.room.config.reconnect_grace_secs);
+        let expired = test.clock.sleep(grace);
+        let (reply, kept) = oneshot::channel();
+        test.room
+            .handle_command(RoomCommand::Disconnected {
+                id: 2,
+                deadline: test.clock.millis_in(grace),
+                reply,
+            })
+            .await;
+        assert!(kept.await.unwrap());
+        assert!(test.room.paused_at.is_some());
+        let dropped: ClientDisconnectPacket = test.expect(1, ClientDisconnectPacket::ID);
+        assert_eq!(dropped.id, "2");
+
+        test.clock.advance(grace);
+        expired.await;
+        test.room
+            .handle_command(RoomCommand::ReconnectExpired(2))
+            .await;
+
+        let win: WinPacket = test.expect(1, WinPacket::ID);
+        assert_eq!(win.role, PlayerRole::One as u32);
+        assert_eq!(win.win_type, WinType::Disconnect as u32);
+        assert!(!test.was_sent(2, WinPacket::ID));
+        assert!(test.room.member(2).is_none());
+        assert!(test.room.game_ended);
+    }
+
+    /// Places the pigs of a game against the expert computer player
+    /// and returns both setups with the first move it picks
+    async fn computer_game(seed: u64) -> (Vec<(u8, Pig)>, Vec<(u8, Pig)>, Move) {
+        let modes = ModeRegistry::builtin().unwrap();
+        let mut settings = GameRoomSettings::from_mode(modes.get(GameMode(5)).unwrap());
+        settings.bot = Some(Difficulty::Expert);
+        let config = CliConfig {
+            seed: Some(seed),
+            max_iterations: Some(20),
+            ignore_turns: true,
+            ..CliConfig::default()
+        };
+        let mut test = TestRoom::with_config(settings, config);
+        test.room.members.truncate(1);
+
+        test.room.in_game = true;
+        test.room.placement_expired().await.unwrap();
+        let setup = |board: &Board| board.iter().map(|x| (x.location, x.pig)).collect();
+        let placed = setup(&test.room.members[0].player.board);
+        let bot = setup(&test.room.bot.as_ref().unwrap().board);
+
+        test.room.current_turn = PlayerRole::Two;
+        test.room.start_player_turn(false).await;
+        test.tick().await;
+        test.clock
+            .advance(Duration::from_millis(COMPUTER_MOVE_DELAY_MS));
+        match test.commands.recv().await.unwrap() {
+            RoomCommand::Tick(_ticker, Tick::ComputerMove(mv)) => (placed, bot, mv),
+            _ => panic!("expected the computer move"),
+        }
+    }
+
+    #[tokio::test]
+    async fn computer_games_replay_with_a_seed() {
+        let first = computer_game(9).await;
+        assert_eq!(first, computer_game(9).await);
+        assert_ne!(first.1, computer_game(10).await.1);
+    }
 }
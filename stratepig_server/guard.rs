@@ -1,28 +1,37 @@
-use crate::error::StratepigError;
-use crate::packet::BaseGuardPacket;
+use crate::error::{ErrorCode, StratepigError};
+use crate::gameroom::GameRoom;
 use crate::GameServer;
 use dyn_clone::{clone_trait_object, DynClone};
-use stratepig_core::{Packet, PacketBody};
+use stratepig_core::Packet;
 
-pub trait Guard: DynClone + 'static {
-    fn guard(&self, id: usize, packet: Packet, server: &GameServer) -> Result<(), StratepigError>;
+/// Checks a packet before it is handled, either by the server or by the room of the client
+pub trait Guard<T>: DynClone + Send + Sync + 'static {
+    fn guard(&self, id: usize, packet: Packet, ctx: &T) -> Result<(), StratepigError>;
     fn name(&self) -> &'static str;
 }
 
-clone_trait_object!(Guard);
+clone_trait_object!(<T> Guard<T>);
 
 #[derive(Clone, Debug)]
 pub struct InRoomGuard;
 
-impl Guard for InRoomGuard {
-    fn guard(&self, id: usize, packet: Packet, server: &GameServer) -> Result<(), StratepigError> {
-        let data = BaseGuardPacket::deserialize(&packet.body)?;
-        if id.to_string() != data.my_id {
-            return Err(StratepigError::AssumeWrongId);
+impl Guard<GameServer> for InRoomGuard {
+    fn guard(&self, id: usize, _packet: Packet, server: &GameServer) -> Result<(), StratepigError> {
+        match server.get_client(id) {
+            Some(client) if server.get_room(client.game_room_id).is_some() => Ok(()),
+            _ => Err(StratepigError::MissingContext),
         }
+    }
+
+    fn name(&self) -> &'static str {
+        "Must be in a room"
+    }
+}
 
-        let ctx = server.get_context(id);
-        if let None = ctx {
+impl Guard<GameRoom> for InRoomGuard {
+    fn guard(&self, id: usize, _packet: Packet, room: &GameRoom) -> Result<(), StratepigError> {
+        // The client may have left before the server knew
+        if room.member(id).is_none() {
             return Err(StratepigError::MissingContext);
         }
 
@@ -37,24 +46,13 @@ impl Guard for InRoomGuard {
 #[derive(Clone, Debug)]
 pub struct InGameGuard;
 
-impl Guard for InGameGuard {
-    fn guard(&self, id: usize, packet: Packet, server: &GameServer) -> Result<(), StratepigError> {
-        let data = BaseGuardPacket::deserialize(&packet.body)?;
-        if id.to_string() != data.my_id {
-            return Err(StratepigError::AssumeWrongId);
-        }
-
-        let ctx = server.get_context(id);
-        if let None = ctx {
+impl Guard<GameRoom> for InGameGuard {
+    fn guard(&self, id: usize, _packet: Packet, room: &GameRoom) -> Result<(), StratepigError> {
+        // Members always have a player object
+        if room.member(id).is_none() {
             return Err(StratepigError::MissingContext);
         }
 
-        let (client, _room) = ctx.unwrap();
-
-        if client.player.as_ref().is_none() {
-            return Err(StratepigError::with("missing player object on client"));
-        }
-
         Ok(())
     }
 
@@ -66,26 +64,17 @@ impl Guard for InGameGuard {
 #[derive(Clone, Debug)]
 pub struct InGameStrictGuard;
 
-impl Guard for InGameStrictGuard {
-    fn guard(&self, id: usize, packet: Packet, server: &GameServer) -> Result<(), StratepigError> {
-        let data = BaseGuardPacket::deserialize(&packet.body)?;
-        if id.to_string() != data.my_id {
-            return Err(StratepigError::AssumeWrongId);
-        }
-
-        let ctx = server.get_context(id);
-        if let None = ctx {
+impl Guard<GameRoom> for InGameStrictGuard {
+    fn guard(&self, id: usize, _packet: Packet, room: &GameRoom) -> Result<(), StratepigError> {
+        if room.member(id).is_none() {
             return Err(StratepigError::MissingContext);
         }
 
-        let (client, room) = ctx.unwrap();
-
-        if client.player.as_ref().is_none() {
-            return Err(StratepigError::with("missing player object on client"));
-        }
-
-        if room.inner().game_phase != 2 || room.inner().game_ended {
-            return Err(StratepigError::with("room not in correct state"));
+        if room.game_phase != 2 || room.game_ended {
+            return Err(StratepigError::coded(
+                ErrorCode::WrongState,
+                "room not in correct state",
+            ));
         }
 
         Ok(())
@@ -95,3 +84,22 @@ impl Guard for InGameStrictGuard {
         "Must be in a game (not in placement or endgame state)"
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct HelloGuard;
+
+impl Guard<GameServer> for HelloGuard {
+    fn guard(&self, id: usize, _packet: Packet, server: &GameServer) -> Result<(), StratepigError> {
+        match server.get_client(id) {
+            Some(client) if client.protocol.is_some() => Ok(()),
+            _ => Err(StratepigError::coded(
+                ErrorCode::Incompatible,
+                "client protocol version is not compatible",
+            )),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Must have a compatible protocol version"
+    }
+}
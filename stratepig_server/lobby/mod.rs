@@ -1,15 +1,14 @@
 use std::convert::TryFrom;
 use stratepig_core::{Packet, PacketBody};
 
-use crate::constants;
+use crate::error::ErrorCode;
 use crate::gameroom;
-use crate::gameroom::{GameMode, GameRoomError};
+use crate::gameroom::{GameMode, GameRoom, GameRoomError};
 use crate::packet::{
-    GameRequestDefaultPacket, RoomTimerUpdatePacket, UpdatePigIconPacket, UpdatePigItemValuePacket,
+    GameRequestDefaultPacket, UpdatePigIconPacket, UpdatePigItemValuePacket,
     UpdateReadyStatePacket, UpdateSettingsValue,
 };
-use crate::player::{PlayerRole, RoomPlayer};
-use crate::util::unix_now;
+use crate::player::{Member, PlayerRole, RoomPlayer};
 use crate::GameServer;
 use crate::StratepigError;
 mod send;
@@ -28,16 +27,14 @@ impl GameServer {
         macro_rules! reject {
             () => {
                 self.fail_create_game(id).await;
-                return Err(StratepigError::with("failed to create game"));
+                return Err(StratepigError::coded(
+                    ErrorCode::BadRequest,
+                    "failed to create game",
+                ));
             };
         }
 
-        if data.my_id != id.to_string() {
-            return Err(StratepigError::AssumeWrongId);
-        }
-        if data.username.trim() == "".to_owned()
-            || data.username.len() > constants::MAX_USERNAME_LENGTH as usize
-        {
+        if data.username.trim() == "" || data.username.len() > self.config.max_username_length {
             reject!();
         }
         if data.icon < 0 || data.icon >= 13 {
@@ -45,105 +42,80 @@ impl GameServer {
         }
 
         if data.is_hosting {
-            let client = self.all_clients.get(&id).unwrap();
-            let endpoint = client.endpoint;
-
-            let room = self
-                .create_room_from_data(data.data_null, &mut packet)
-                .await;
+            let room = self.create_room_from_data(data.data_null, &mut packet);
 
             if let Err(err) = room {
                 let err = String::from(err);
-                drop(room); // We need to drop the room first before we immutably borrow self
-                if let "" = &err[..] {
+                if err[..].is_empty() {
                     reject!();
                 } else {
                     self.err_join_game(id, &err).await;
-                    return Err(StratepigError::with("failed to create game"));
+                    return Err(StratepigError::coded(
+                        ErrorCode::BadRequest,
+                        "failed to create game",
+                    ));
                 }
             }
 
-            let room = room.unwrap();
-            let room_id = room.id();
-            room.get().write().unwrap().client_ids.push((id, endpoint));
-
-            drop(room);
-
-            let client = self.all_clients.get_mut(&id).unwrap();
-            client.set_game_room(room_id);
-            client.room_player = Some(RoomPlayer::new(
-                PlayerRole::One,
-                data.username,
-                data.icon as u8,
-                client,
-            ));
-
-            let reference = self.get_room(room_id).unwrap();
-
-            self.initialize_player(id, PlayerRole::One).await;
-            self.room_player_add(&reference).await;
-            self.send_game_info(&reference, Some(id)).await;
+            // Nobody else knows the code yet, so this cannot fail
+            let room_id = room.unwrap();
+            let _ = self
+                .join_room(room_id, id, data.username, data.icon as u8, true)
+                .await;
         } else {
-            let room_join = self.try_join_room(&data.code);
-            match room_join {
-                Err(err) => {
-                    match err {
-                        GameRoomError::NotFound => {
-                            self.err_join_game(id, "Could not find the game you were looking for.")
-                                .await
-                        }
-                        GameRoomError::Started => {
-                            self.err_join_game(id, "That game has already started.")
-                                .await
-                        }
-                        GameRoomError::Full => self.err_join_game(id, "That game is full.").await,
+            let room_id = self.get_room_by_code(&data.code).unwrap_or(0);
+            let room_join = self
+                .join_room(room_id, id, data.username, data.icon as u8, false)
+                .await;
+            if let Err(err) = room_join {
+                match err {
+                    GameRoomError::NotFound => {
+                        self.err_join_game(id, "Could not find the game you were looking for.")
+                            .await
                     }
-                    return Ok(());
-                }
-                Ok(_) => {
-                    let found = room_join.unwrap();
-                    let read = found.inner();
-                    let room_id = read.id;
-                    let safe_username = self.generate_safe_username(&found, &data.username);
-                    let client_count = read.client_ids.len();
-
-                    let player_role;
-                    if client_count == 0 {
-                        player_role = PlayerRole::One;
-                    } else {
-                        player_role = PlayerRole::Two;
+                    GameRoomError::Started => {
+                        self.err_join_game(id, "That game has already started.")
+                            .await
                     }
+                    GameRoomError::Full => self.err_join_game(id, "That game is full.").await,
+                }
+            }
+        }
 
-                    drop(read);
-                    drop(found);
-
-                    let client = self.all_clients.get_mut(&id).unwrap();
-                    let endpoint = client.endpoint;
-                    client.set_game_room(room_id);
-                    client.room_player = Some(RoomPlayer::new(
-                        player_role,
-                        safe_username,
-                        data.icon as u8,
-                        client,
-                    ));
-
-                    self.get_room(room_id)
-                        .unwrap()
-                        .get()
-                        .write()
-                        .unwrap()
-                        .client_ids
-                        .push((id, endpoint));
-
-                    let reference = self.get_room(room_id).unwrap();
+        Ok(())
+    }
+}
 
-                    self.initialize_player(id, player_role).await;
-                    self.room_player_add(&reference).await;
-                    self.send_game_info(&reference, Some(id)).await;
-                }
+impl GameRoom {
+    /// Adds a client to the room, with the first one in being the host
+    pub async fn join(
+        &mut self,
+        id: usize,
+        username: String,
+        icon: u8,
+        hosting: bool,
+    ) -> Result<(), GameRoomError> {
+        if !hosting {
+            if self.in_game {
+                return Err(GameRoomError::Started);
+            } else if self.members.len() >= 2 || self.settings.bot.is_some() {
+                return Err(GameRoomError::Full);
             }
         }
 
+        let player_role = if self.members.is_empty() {
+            PlayerRole::One
+        } else {
+            PlayerRole::Two
+        };
+        let safe_username = self.generate_safe_username(&username);
+        let room_player = RoomPlayer::new(safe_username, icon);
+        self.members.push(Member::new(id, player_role, room_player));
+        self.store_seen();
+
+        self.initialize_player(id, player_role).await;
+        self.room_player_add().await;
+        self.send_game_info(Some(id)).await;
         Ok(())
     }
 
@@ -152,12 +124,8 @@ impl GameServer {
         id: usize,
         _packet: Packet,
     ) -> Result<(), StratepigError> {
-        let (client, room) = self.get_context(id).unwrap();
-        let endpoint = client.endpoint;
-        let room_id = room.id();
-        drop(room);
-
-        self.handle_client_disconnect(room_id, id, endpoint).await;
+        self.forfeit_disconnected(id).await;
+        self.remove_member(id).await;
         Ok(())
     }
 
@@ -167,47 +135,29 @@ impl GameServer {
         packet: Packet,
     ) -> Result<(), StratepigError> {
         let data = UpdateReadyStatePacket::deserialize(&packet.body)?;
-        let (_client, room) = self.get_context(id).unwrap();
-        let room_id = room.id();
 
-        if room.inner().in_game {
-            return Err(StratepigError::with("cannot update ready state in game"));
+        if self.in_game {
+            return Err(StratepigError::coded(
+                ErrorCode::WrongState,
+                "cannot update ready state in game",
+            ));
         }
 
-        drop(room);
-
-        self.all_clients
-            .get_mut(&id)
-            .unwrap()
-            .room_player
-            .as_mut()
-            .unwrap()
-            .ready = data.ready;
-        let reference = self.get_room(room_id).unwrap();
-        self.room_update_ready_state(&reference, id, data.ready)
-            .await;
+        self.member_mut(id).unwrap().room_player.ready = data.ready;
+        self.room_update_ready_state(id, data.ready).await;
 
         if data.ready {
-            if self.config.one_player {
-                reference.start(self, 1).await;
-            } else {
-                // If there is any better way to do this, please let me know
-                if let Some(res) = self.get_other_player(&reference, id) {
-                    if let Some(player) = &res.room_player {
-                        if player.ready {
-                            reference.start(self, 5).await;
-                        }
-                    }
-                }
+            if self.settings.bot.is_some() {
+                self.start(1).await;
+            } else if self
+                .other_member(id)
+                .is_some_and(|other| other.room_player.ready)
+            {
+                self.start(self.config.countdown_secs).await;
             }
         } else {
-            reference.cancel_start();
-
-            let packet = RoomTimerUpdatePacket {
-                timestamp: -1,
-                server_now: unix_now(),
-            };
-            self.message_room(&reference, packet).await;
+            self.cancel_start();
+            self.update_room_timer(-1).await;
         }
 
         Ok(())
@@ -219,25 +169,16 @@ impl GameServer {
         packet: Packet,
     ) -> Result<(), StratepigError> {
         let data = UpdatePigIconPacket::deserialize(&packet.body)?;
-        let (_client, room) = self.get_context(id).unwrap();
-        let room_id = room.id();
-
-        drop(room);
 
         if data.icon > 12 {
-            return Err(StratepigError::with("icon out-of-bounds"));
+            return Err(StratepigError::coded(
+                ErrorCode::BadRequest,
+                "icon out-of-bounds",
+            ));
         }
 
-        self.all_clients
-            .get_mut(&id)
-            .unwrap()
-            .room_player
-            .as_mut()
-            .unwrap()
-            .icon = data.icon as u8;
-        let reference = self.get_room(room_id).unwrap();
-
-        self.update_icon(&reference, id, data.icon).await;
+        self.member_mut(id).unwrap().room_player.icon = data.icon as u8;
+        self.update_icon(id, data.icon).await;
 
         Ok(())
     }
@@ -248,56 +189,51 @@ impl GameServer {
         packet: Packet,
     ) -> Result<(), StratepigError> {
         let data = UpdateSettingsValue::deserialize(&packet.body)?;
-        let (client, room) = self.get_context(id).unwrap();
 
-        if client.player.as_ref().unwrap().role == PlayerRole::One {
+        if self.player(id).unwrap().role == PlayerRole::One {
             let key = &(u8::try_from(data.settings_id).unwrap_or(0));
 
-            if data.settings_id <= 0 {
-                let mut current_value = room.inner().settings.game_mode as u8;
-                if data.increased {
-                    current_value += 1;
-                    if current_value > GameMode::MAX {
-                        current_value = 1;
-                    }
-                } else {
-                    current_value -= 1;
-                    if current_value < 1 {
-                        current_value = GameMode::MAX;
-                    }
-                }
-
-                let current_type = GameMode::from(current_value);
-                room.get().write().unwrap().settings.game_mode = current_type;
+            if data.settings_id == 0 {
+                let previous_mode = self.settings.game_mode;
+                let current_type = self.modes.cycle(previous_mode, data.increased);
+                self.settings.game_mode = current_type;
 
-                self.update_settings_value(&room, data.settings_id, current_value as u32)
+                self.update_settings_value(data.settings_id, current_type.0 as u32)
                     .await;
 
-                if current_type != GameMode::Custom {
-                    let config = gameroom::get_pig_config_for_mode(current_type).unwrap();
-                    let settings_vars = gameroom::get_settings_vars(current_type);
-
-                    let mut write = room.get().write().unwrap();
-                    write.settings.turn_time = settings_vars.turn_time;
-                    write.settings.buffer_time = settings_vars.buffer_time;
-                    write.settings.pig_config = config.clone();
-                    drop(write);
+                if let Some(def) = self.modes.get(current_type) {
+                    let config = def.pig_config.clone();
+
+                    self.settings.placement_time = def.placement_time;
+                    self.settings.turn_time = def.turn_time;
+                    self.settings.buffer_time = def.buffer_time;
+                    self.settings.rules = def.rules;
+                    self.settings.pig_config = config.clone();
+
+                    // Turn and buffer time are sent along with the pig config
+                    self.update_settings_value(1, self.settings.placement_time)
+                        .await;
+                    for settings_id in 4..=gameroom::MAX_SETTINGS_ID {
+                        let value = self.settings.value(settings_id).unwrap();
+                        self.update_settings_value(settings_id, value).await;
+                    }
+                    self.update_config_bulk(config).await;
+                }
 
-                    self.update_config_bulk(&room, config).await;
+                // The board layout is only sent along with the rest of the game info
+                if self.modes.layout(previous_mode) != self.modes.layout(current_type) {
+                    self.send_game_info(None).await;
                 }
-            } else if data.settings_id <= 3 {
-                let mut current_value = match data.settings_id {
-                    1 => room.inner().settings.placement_time,
-                    2 => room.inner().settings.turn_time,
-                    3 => room.inner().settings.buffer_time,
-                    _ => 0,
-                } as i32;
+            } else if data.settings_id == gameroom::BOT_SETTINGS_ID && self.members.len() > 1 {
+                return Ok(());
+            } else if data.settings_id <= gameroom::MAX_SETTINGS_ID {
+                let mut current_value = self.settings.value(data.settings_id).unwrap() as i32;
 
                 let group = gameroom::SETTINGS_GROUPS.get(key).unwrap();
 
                 if data.increased {
                     current_value += group.interval as i32;
-                    if current_value as i32 > group.max_val {
+                    if current_value > group.max_val {
                         if group.loopable {
                             current_value = group.min_val;
                         } else {
@@ -306,7 +242,7 @@ impl GameServer {
                     }
                 } else {
                     current_value -= group.interval as i32;
-                    if (current_value as i32) < group.min_val {
+                    if current_value < group.min_val {
                         if group.loopable {
                             current_value = group.max_val;
                         } else {
@@ -315,14 +251,10 @@ impl GameServer {
                     }
                 }
 
-                match data.settings_id {
-                    1 => room.get().write().unwrap().settings.placement_time = current_value as u32,
-                    2 => room.get().write().unwrap().settings.turn_time = current_value as u32,
-                    3 => room.get().write().unwrap().settings.buffer_time = current_value as u32,
-                    _ => {}
-                };
+                self.settings
+                    .set_value(data.settings_id, current_value as u32);
 
-                self.update_settings_value(&room, data.settings_id, current_value as u32)
+                self.update_settings_value(data.settings_id, current_value as u32)
                     .await;
             }
         }
@@ -336,19 +268,19 @@ impl GameServer {
         packet: Packet,
     ) -> Result<(), StratepigError> {
         let data = UpdatePigItemValuePacket::deserialize(&packet.body)?;
-        let (client, room) = self.get_context(id).unwrap();
 
         if let Pig::Empty = Pig::from(data.pig) {
-            return Err(StratepigError::with("invalid pig"));
+            return Err(StratepigError::coded(ErrorCode::BadRequest, "invalid pig"));
         }
 
-        if client.player.as_ref().unwrap().role == PlayerRole::One {
-            let mut pig_config = room.inner().settings.pig_config.clone();
-            let total: u32 = pig_config.iter().map(|(_k, v)| *v as u32).sum();
+        if self.player(id).unwrap().role == PlayerRole::One {
+            let mut pig_config = self.settings.pig_config.clone();
+            let total: u32 = pig_config.values().map(|v| *v as u32).sum();
             let pig = Pig::from(data.pig);
 
             if data.increased {
-                if total + 1 > 40 {
+                let setup_tiles = self.modes.layout(GameMode::CUSTOM).setup_tiles();
+                if total + 1 > setup_tiles as u32 {
                     return Ok(());
                 }
                 let current = *pig_config.get(&pig).unwrap();
@@ -362,18 +294,23 @@ impl GameServer {
             }
 
             let updated = *pig_config.get(&pig).unwrap();
-            let mut write = room.get().write().unwrap();
-            write.settings.game_mode = GameMode::Custom;
-            write.settings.pig_config = pig_config;
-            drop(write);
+            let previous_mode = self.settings.game_mode;
+            self.settings.game_mode = GameMode::CUSTOM;
+            self.settings.pig_config = pig_config;
 
-            self.update_settings_value(&room, 0, GameMode::Custom as u32)
+            self.update_settings_value(0, GameMode::CUSTOM.0 as u32)
                 .await;
-            self.update_pig_item(&room, data.pig, updated as u32).await;
+            self.update_pig_item(data.pig, updated as u32).await;
+            if self.modes.layout(previous_mode) != self.modes.layout(GameMode::CUSTOM) {
+                self.send_game_info(None).await;
+            }
 
             return Ok(());
         }
 
-        return Err(StratepigError::with("invalid authority"));
+        Err(StratepigError::coded(
+            ErrorCode::NotHost,
+            "invalid authority",
+        ))
     }
 }
@@ -7,125 +7,129 @@ impl GameServer {
         self.message_one(id, packet).await;
     }
 
+    pub async fn err_join_game(&self, id: usize, message: &str) {
+        let packet = ErrJoinGamePacket {
+            msg: message.to_owned(),
+        };
+        self.message_one(id, packet).await;
+    }
+}
+
+impl GameRoom {
     pub async fn initialize_player(&self, id: usize, role: PlayerRole) {
         let packet = ClientInfoPacket { role: role as u32 };
         self.message_one(id, packet).await;
     }
 
-    pub async fn room_player_add(&self, room: &GameRoom) {
-        let read = room.inner();
-
-        for id in read.client_ids.iter() {
-            let client = self.all_clients.get(&id.0).unwrap();
-            let room_player = client.room_player.as_ref().unwrap();
+    pub async fn room_player_add(&self) {
+        for member in self.members.iter() {
+            let room_player = &member.room_player;
 
             let packet = RoomPlayerAddPacket {
-                id: client.id.to_string(),
-                client_count: read.client_ids.len() as i32,
+                id: member.id.to_string(),
+                client_count: self.members.len() as i32,
                 username: room_player.username.clone(),
                 ready: room_player.ready,
                 icon: room_player.icon as i32,
             };
 
-            self.message_room(room, packet).await;
+            self.message_room(packet).await;
         }
     }
 
-    pub async fn client_disconnected(&self, room: &GameRoom, id: usize) {
+    pub async fn client_disconnected(&self, id: usize) {
         let packet = ClientDisconnectPacket {
             id: id.to_string(),
-            timestamp: 0, // TODO: actually calculate elapsed unix timestamp
+            // The client is gone for good, so there is nothing left to wait for
+            timestamp: self.clock.now_millis() as u64,
         };
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
-    pub async fn send_game_info(&self, room: &GameRoom, id: Option<usize>) {
-        let inner = room.inner();
+    pub async fn send_game_info(&self, id: Option<usize>) {
+        let settings = &self.settings;
+        let layout = self.modes.layout(settings.game_mode);
 
         let packet = GameInfoPacket {
-            code: inner.code.clone(),
-            game_mode: inner.settings.game_mode as i32,
-            placement_time: inner.settings.placement_time,
-            turn_time: inner.settings.turn_time,
-            buffer_time: inner.settings.buffer_time,
-            pig_config: inner
-                .settings
+            code: self.code.clone(),
+            game_mode: settings.game_mode.0 as i32,
+            placement_time: settings.placement_time,
+            turn_time: settings.turn_time,
+            buffer_time: settings.buffer_time,
+            chemist_ability: settings.rules.chemist.map_or(0, |x| x as u32),
+            pig_config: settings
                 .pig_config
                 .iter()
-                .map(|(key, value)| {
-                    return (*key as u32, *value as u32);
-                })
+                .map(|(key, value)| (*key as u32, *value as u32))
                 .collect(),
+            board_width: layout.width,
+            board_height: layout.height,
+            lakes: layout.lakes,
+            setup_rows: layout.setup_rows,
+            scout_move_and_strike: settings.rules.scout_move_and_strike,
+            single_use_bombs: settings.rules.single_use_bombs,
+            attacker_wins_ties: settings.rules.attacker_wins_ties,
+            spy_attack_only: settings.rules.spy_attack_only,
+            repetition: settings.rules.repetition as u32,
+            bot: settings.bot.map_or(0, |x| x as u32),
+            forfeit_placement: settings.forfeit_placement,
         };
 
         if let Some(id) = id {
             self.message_one(id, packet).await;
         } else {
-            self.message_room(room, packet).await;
+            self.message_room(packet).await;
         }
     }
 
-    pub async fn err_join_game(&self, id: usize, message: &str) {
-        let packet = ErrJoinGamePacket {
-            msg: message.to_owned(),
-        };
-        self.message_one(id, packet).await;
-    }
-
-    pub async fn room_update_ready_state(&self, room: &GameRoom, id: usize, ready: bool) {
+    pub async fn room_update_ready_state(&self, id: usize, ready: bool) {
         let packet = RoomPlayerUpdatedReadyStatePacket {
             id: id.to_string(),
             ready,
         };
 
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
-    pub async fn update_room_timer(&self, room: &GameRoom, seconds: i32) {
+    pub async fn update_room_timer(&self, seconds: i32) {
         let packet = RoomTimerUpdatePacket {
             timestamp: seconds as i128,
-            server_now: util::unix_now(),
+            server_now: self.clock.now_millis(),
         };
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
-    pub async fn update_icon(&self, room: &GameRoom, id: usize, icon: u32) {
+    pub async fn update_icon(&self, id: usize, icon: u32) {
         let packet = UpdatedPigIconPacket {
             id: id.to_string(),
             icon: icon as i32,
         };
 
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
-    pub async fn update_settings_value(&self, room: &GameRoom, id: u32, value: u32) {
+    pub async fn update_settings_value(&self, id: u32, value: u32) {
         let packet = SettingsValueChangedPacket { id, value };
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
-    pub async fn update_pig_item(&self, room: &GameRoom, pig: u32, amount: u32) {
+    pub async fn update_pig_item(&self, pig: u32, amount: u32) {
         let packet = PigItemValueChangedPacket { pig, amount };
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 
-    pub async fn update_config_bulk(
-        &self,
-        room: &GameRoom,
-        config: HashMap<stratepig_game::Pig, u8>,
-    ) {
-        let read = room.inner();
+    pub async fn update_config_bulk(&self, config: HashMap<stratepig_game::Pig, u8>) {
+        let read = &self.settings;
 
         let packet = PigConfigValueChangedPacket {
-            turn_time: read.settings.turn_time,
-            buffer_time: read.settings.buffer_time,
+            turn_time: read.turn_time,
+            buffer_time: read.buffer_time,
             pig_config: config
                 .iter()
-                .map(|(key, value)| {
-                    return (*key as u32, *value as u32);
-                })
+                .map(|(key, value)| (*key as u32, *value as u32))
                 .collect(),
         };
 
-        self.message_room(room, packet).await;
+        self.message_room(packet).await;
     }
 }
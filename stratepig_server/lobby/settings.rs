@@ -4,16 +4,17 @@ use std::convert::TryFrom;
 use stratepig_core::{Packet, PacketBody};
 use stratepig_game::Pig;
 
-use crate::gameroom::{self, GameMode, GameRoom, SettingsGroup};
+use crate::gameroom::{self, GameMode, SettingsGroup};
 use crate::packet::GameRequestFullPacket;
 use crate::GameServer;
 
 impl GameServer {
-    pub async fn create_room_from_data(
+    /// Starts a room with the settings sent by the host, returning its id
+    pub fn create_room_from_data(
         &mut self,
         data_null: bool,
         packet: &mut Packet,
-    ) -> Result<impl std::ops::Deref<Target = GameRoom> + '_, &str> {
+    ) -> Result<usize, &str> {
         macro_rules! err {
             () => {
                 return Err("invalid config");
@@ -21,17 +22,13 @@ impl GameServer {
         }
 
         if !data_null {
-            let data = GameRequestFullPacket::deserialize(&packet.body).or_else(|_e| {
-                return Err("invalid packet");
-            })?;
+            let data =
+                GameRequestFullPacket::deserialize(&packet.body).map_err(|_e| "invalid packet")?;
 
-            let game_mode = match data.game_mode {
-                1 => GameMode::Original,
-                2 => GameMode::Infiltrator,
-                3 => GameMode::Duel,
-                4 => GameMode::Custom,
-                _ => GameMode::Original,
-            };
+            let mut game_mode = GameMode(u8::try_from(data.game_mode).unwrap_or(0));
+            if game_mode != GameMode::CUSTOM && self.modes.get(game_mode).is_none() {
+                game_mode = self.modes.default_mode().mode;
+            }
 
             let placement_group = gameroom::SETTINGS_GROUPS.get(&1).unwrap();
             let placement_secs = sanitize_setting(data.placement_secs as u32, placement_group);
@@ -46,23 +43,20 @@ impl GameServer {
                 .map(|(pig, amt)| {
                     let pig = Pig::from(pig as u32);
                     let amt = u8::try_from(amt).unwrap_or(0);
-                    return (pig, amt);
+                    (pig, amt)
                 })
                 .collect();
 
             for i in 0..13 {
                 let key = Pig::from(i);
-                if !pig_config.contains_key(&key) {
-                    pig_config.insert(key, 0); // Fill empty spaces with 0
-                }
+                pig_config.entry(key).or_insert(0);
             }
 
-            pig_config = match gameroom::get_pig_config_for_mode(game_mode) {
-                Some(data) => data,
-                None => pig_config,
-            };
+            if let Some(def) = self.modes.get(game_mode) {
+                pig_config = def.pig_config.clone();
+            }
 
-            if game_mode == GameMode::Custom {
+            if game_mode == GameMode::CUSTOM {
                 if pig_config.keys().len() > 13 {
                     err!();
                 }
@@ -75,26 +69,31 @@ impl GameServer {
                 for val in pig_config.values() {
                     total += *val;
                 }
-                if total > 40 || total <= 0 {
+                let setup_tiles = self.modes.layout(game_mode).setup_tiles();
+                if total > setup_tiles || total == 0 {
                     err!();
                 }
             }
 
-            let room = self.new_room()?;
-            let mut write = room.get().write().unwrap();
-            write.settings.game_mode = game_mode;
-            write.settings.placement_time = placement_secs;
-            write.settings.turn_time = turn_secs;
-            write.settings.buffer_time = buffer_secs;
-            write.settings.pig_config = pig_config;
-            drop(write);
+            let rules = self
+                .modes
+                .get(game_mode)
+                .unwrap_or_else(|| self.modes.default_mode())
+                .rules;
 
-            return Ok(room);
-        } else {
-            let room = self.new_room()?;
-            room.load_default_settings();
+            let mut settings = self.default_settings();
+            settings.game_mode = game_mode;
+            settings.rules = rules;
+            settings.placement_time = placement_secs;
+            settings.turn_time = turn_secs;
+            settings.buffer_time = buffer_secs;
+            settings.pig_config = pig_config;
 
-            return Ok(room);
+            self.new_room(settings)
+        } else {
+            // New rooms start out with the default mode
+            let settings = self.default_settings();
+            self.new_room(settings)
         }
     }
 }
@@ -102,7 +101,7 @@ impl GameServer {
 fn sanitize_setting(mut provided: u32, setting: &SettingsGroup) -> u32 {
     if provided > setting.max_val as u32
         || (provided < setting.min_val as u32)
-        || (provided % setting.interval != 0)
+        || !provided.is_multiple_of(setting.interval)
     {
         provided = setting.default as u32;
     }
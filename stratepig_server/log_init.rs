@@ -1,8 +1,8 @@
 use simplelog::*;
 
-pub fn init() {
+pub fn init(level: LevelFilter) {
     CombinedLogger::init(vec![TermLogger::new(
-        LevelFilter::Info,
+        level,
         Config::default(),
         TerminalMode::Stdout,
         ColorChoice::Always,
@@ -1,24 +1,26 @@
-use log::{info, trace, warn};
+use log::{error, info, trace, warn, LevelFilter};
 use message_io::network::{Endpoint, Transport};
 use message_io::node::{
     self, NodeHandler, NodeListener, StoredNetEvent, StoredNodeEvent as NodeEvent,
 };
-use parking_lot::{Mutex, MutexGuard};
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::future::Future;
-use std::ops::Deref;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::thread;
 use std::time;
+use tokio::sync::oneshot;
 use vec_map::VecMap;
 
 use stratepig_cli::{self, CliConfig};
-use stratepig_core::{Packet, PacketBody};
-use stratepig_macros;
+use stratepig_core::{packet_name, Direction, FrameDecoder, Packet, PacketBody};
+use stratepig_game::Difficulty;
 
 mod client;
+mod clock;
 mod constants;
 mod error;
 mod game;
@@ -26,18 +28,43 @@ mod gameroom;
 mod guard;
 mod lobby;
 mod log_init;
-mod macros;
+mod modes;
 mod packet;
 mod player;
+mod session;
+mod tls;
 mod util;
 mod version;
 mod win;
 use client::Client;
+use clock::{SharedClock, SystemClock};
 use error::StratepigError;
-use gameroom::{GameRoom, GameRoomError};
-use guard::{Guard, InGameGuard, InGameStrictGuard, InRoomGuard};
-use packet::{ClientMessage::*, *};
-use player::{Player, PlayerRole};
+use gameroom::{
+    GameRoom, GameRoomError, GameRoomSettings, RoomCommand, RoomContext, RoomHandle, RoomHandlers,
+};
+use guard::{Guard, HelloGuard, InGameGuard, InGameStrictGuard, InRoomGuard};
+use modes::ModeRegistry;
+use packet::*;
+use player::PlayerRole;
+
+/// Events the server sends to itself from tickers, rooms and other tasks
+#[derive(Debug)]
+pub enum ServerSignal {
+    /// A dropped client did not resume in time
+    ReconnectExpired(usize),
+    /// Time to check for idle clients and send keep alives
+    Heartbeat,
+    /// Time for rooms to check whether they were left unused
+    Prune,
+    /// Stats were asked for on the command line
+    Stats,
+    /// A client is no longer in the room
+    LeftRoom(usize, usize),
+    /// The task of a room has ended
+    RoomClosed(usize),
+    /// A room has a packet for one of its clients
+    Outgoing(usize, Vec<u8>),
+}
 
 type PacketHandler = fn(
     &mut GameServer,
@@ -45,26 +72,54 @@ type PacketHandler = fn(
     Packet,
 ) -> Pin<Box<dyn Future<Output = Result<(), StratepigError>> + '_>>;
 
+/// Keeps track of connections and passes the packets of clients in a room on to the task
+/// of that room, so rooms never wait on each other
 pub struct GameServer {
-    handler: Arc<Mutex<NodeHandler<()>>>,
-    config: CliConfig,
+    handler: NodeHandler<ServerSignal>,
+    config: Arc<CliConfig>,
+    modes: Arc<ModeRegistry>,
+    clock: SharedClock,
     packet_handlers: VecMap<PacketHandler>,
-    guards: VecMap<Option<Box<dyn Guard>>>,
-    endpoints: Arc<Mutex<HashMap<Endpoint, usize>>>,
+    guards: VecMap<Option<Box<dyn Guard<GameServer>>>>,
+    room_handlers: Arc<RoomHandlers>,
+    endpoints: HashMap<Endpoint, usize>,
+    decoders: HashMap<Endpoint, FrameDecoder>,
     all_clients: HashMap<usize, Client>,
     next_client_id: usize,
     free_client_ids: VecDeque<usize>,
-    pub game_rooms: Arc<Mutex<VecMap<GameRoom>>>,
-    free_game_room_ids: Arc<Mutex<VecDeque<usize>>>,
-    next_game_room_id: Arc<Mutex<usize>>,
-    game_room_codes: Arc<Mutex<Vec<String>>>,
+    game_rooms: VecMap<RoomHandle>,
+    free_game_room_ids: VecDeque<usize>,
+    next_game_room_id: usize,
 }
 
-const MAX_ROOMS: usize = 1000;
-const PRUNE_INTERVAL_SECS: u64 = 180;
-const MAX_PRUNE_AGE_SECS: u64 = 300;
-
 impl GameServer {
+    fn new(
+        handler: NodeHandler<ServerSignal>,
+        config: CliConfig,
+        modes: ModeRegistry,
+        clock: SharedClock,
+    ) -> Self {
+        let mut server = Self {
+            handler,
+            config: Arc::new(config),
+            modes: Arc::new(modes),
+            clock,
+            packet_handlers: VecMap::new(),
+            guards: VecMap::new(),
+            room_handlers: Arc::new(room_packet_handlers()),
+            endpoints: HashMap::new(),
+            decoders: HashMap::new(),
+            all_clients: HashMap::new(),
+            next_client_id: 1,
+            free_client_ids: VecDeque::new(),
+            game_rooms: VecMap::new(),
+            next_game_room_id: 0,
+            free_game_room_ids: VecDeque::new(),
+        };
+        server.register_packet_handlers();
+        server
+    }
+
     fn register_packet_handlers(&mut self) {
         macro_rules! register {
             ($id:expr, $p:expr) => {{
@@ -82,44 +137,19 @@ impl GameServer {
             }};
         }
 
-        register!(GameRequestSent, Self::handle_game_request);
-
-        register_guarded!(
-            UpdateReadyState,
-            Self::handle_ready_state_change,
-            InRoomGuard
-        );
-        register_guarded!(UpdatePigIcon, Self::handle_update_icon, InRoomGuard);
-        register_guarded!(
-            UpdateSettingsValue,
-            Self::handle_settings_value_update,
-            InRoomGuard
-        );
-        register_guarded!(
-            UpdatePigItemValue,
-            Self::handle_pig_item_update,
-            InRoomGuard
-        );
-        register_guarded!(
-            FinishedSceneLoad,
-            Self::handle_client_finish_scene_load,
-            InRoomGuard
-        );
-
+        register!(HelloPacket::ID, Self::handle_hello);
+        register!(KeepAliveEchoPacket::ID, Self::handle_keep_alive);
         register_guarded!(
-            GamePlayerReadyData,
-            Self::handle_game_player_ready,
-            InGameGuard
+            GameRequestDefaultPacket::ID,
+            Self::handle_game_request,
+            HelloGuard
         );
-
-        register_guarded!(Surrender, Self::handle_surrender, InGameGuard);
-        register_guarded!(LeaveGame, Self::handle_client_leave, InGameGuard);
-        register_guarded!(PlayAgain, Self::handle_client_play_again, InGameGuard);
-        register_guarded!(Move, Self::move_received, InGameStrictGuard);
+        register_guarded!(ResumePacket::ID, Self::handle_resume, HelloGuard);
     }
 
-    async fn start(&mut self, listener: NodeListener<()>) {
+    async fn start(&mut self, listener: NodeListener<ServerSignal>) {
         self.run_prune_cycle();
+        self.run_heartbeat_cycle();
         // Core loop
         let packet_handlers = self.packet_handlers.clone();
         let guards = self.clone_guards();
@@ -140,19 +170,22 @@ impl GameServer {
                         self.handle_connection(endpoint, id).await;
                     }
                     StoredNetEvent::Message(endpoint, data) => {
-                        if let Ok(header) = stratepig_core::deserialize_packet_header(&data) {
-                            let packet_size =
-                                stratepig_core::PACKET_HEADER_SIZE + (header.size as usize);
-                            let bytes: &[u8] =
-                                &data[stratepig_core::PACKET_HEADER_SIZE..packet_size];
-                            let body = bytes.to_vec();
-                            let packet = Packet { header, body };
-
-                            let endpoints = self.endpoints.lock();
-                            let result = endpoints.get(&endpoint);
-                            if let Some(id) = result {
-                                let id = *id;
-                                drop(endpoints);
+                        // A read can hold part of a packet or several of them
+                        let decoder = self.decoders.entry(endpoint).or_default();
+                        let packets = match decoder.decode(&data) {
+                            Ok(packets) => packets,
+                            Err(err) => {
+                                warn!("Dropping connection sending bad data: {:?}", err);
+                                self.handler.network().remove(endpoint.resource_id());
+                                self.handle_disconnect(endpoint).await;
+                                continue;
+                            }
+                        };
+
+                        for packet in packets {
+                            // Resuming moves the endpoint to another client
+                            let id = self.endpoints.get(&endpoint).copied();
+                            if let Some(id) = id {
                                 self.handle_data(id, packet, &packet_handlers, &guards)
                                     .await;
                             }
@@ -163,259 +196,298 @@ impl GameServer {
                     }
                     _ => {}
                 },
-                _ => {}
+                NodeEvent::Signal(ServerSignal::ReconnectExpired(id)) => {
+                    self.reconnect_expired(id).await;
+                }
+                NodeEvent::Signal(ServerSignal::Heartbeat) => {
+                    self.heartbeat().await;
+                }
+                NodeEvent::Signal(ServerSignal::Prune) => {
+                    for (_id, room) in self.game_rooms.iter() {
+                        room.send(RoomCommand::Prune);
+                    }
+                }
+                NodeEvent::Signal(ServerSignal::Stats) => {
+                    println!("--- SERVER STATS ---");
+                    println!("Number of clients: {}", self.endpoints.len());
+                    println!("Number of rooms: {}", self.game_rooms.len());
+                }
+                NodeEvent::Signal(ServerSignal::LeftRoom(id, room_id)) => {
+                    // The client may have joined another room since
+                    if let Some(client) = self.get_client_mut(id) {
+                        if client.game_room_id == room_id {
+                            client.set_game_room(0);
+                        }
+                    }
+                }
+                NodeEvent::Signal(ServerSignal::RoomClosed(room_id)) => {
+                    self.room_closed(room_id);
+                }
+                NodeEvent::Signal(ServerSignal::Outgoing(id, bytes)) => {
+                    // The client may have disconnected meanwhile
+                    if let Some(client) = self.get_client(id) {
+                        self.handler.network().send(client.endpoint, &bytes);
+                    }
+                }
             }
         }
     }
 
     async fn handle_connection(&mut self, endpoint: Endpoint, id: usize) {
-        self.endpoints.lock().insert(endpoint, id);
-        self.all_clients.insert(id, Client::new(id, endpoint));
+        self.endpoints.insert(endpoint, id);
+        let client = Client::new(id, endpoint, self.clock.now_millis());
+        let session_token = client.session_token.clone();
+        self.all_clients.insert(id, client);
 
         let packet = WelcomePacket {
             version: version::VERSION.to_owned(),
             my_id: id.to_string(),
+            session_token,
+            protocol: version::PROTOCOL_VERSION,
         };
         self.message_one(id, packet).await;
     }
 
-    async fn handle_disconnect(&mut self, endpoint: Endpoint) {
-        let mut endpoints = self.endpoints.lock();
-        let id = endpoints.get(&endpoint);
-        if let Some(id) = id {
-            let id = *id;
-            if let Some(client) = self.all_clients.get(&id) {
-                let client_id = client.id;
-                let game_room_id = client.game_room_id;
-
-                endpoints.remove(&endpoint);
-                drop(endpoints);
-
-                if game_room_id != 0 {
-                    let id = client.id;
-                    self.handle_client_disconnect(game_room_id, id, endpoint)
-                        .await;
-                }
+    pub async fn handle_disconnect(&mut self, endpoint: Endpoint) {
+        self.decoders.remove(&endpoint);
+        let id = match self.endpoints.remove(&endpoint) {
+            Some(id) => id,
+            None => return,
+        };
+        let room_id = match self.get_client(id) {
+            Some(client) => client.game_room_id,
+            None => return,
+        };
 
-                self.all_clients.remove(&client_id);
-            }
+        if room_id != 0 && self.client_dropped(room_id, id).await {
+            return;
         }
+        self.all_clients.remove(&id);
     }
 
-    async fn handle_client_disconnect(&mut self, room_id: usize, id: usize, endpoint: Endpoint) {
-        let result = self.get_room(room_id);
-        if let Some(_) = result {
-            let room = result.unwrap();
-            let mut client_ids = room.inner().client_ids.clone();
-            if !client_ids.contains(&(id, endpoint)) {
-                return;
-            }
-
-            let mut write = room.get().write().unwrap();
+    async fn handle_data(
+        &mut self,
+        id: usize,
+        packet: Packet,
+        handlers: &VecMap<PacketHandler>,
+        guards: &VecMap<Option<Box<dyn Guard<GameServer>>>>,
+    ) {
+        let now = self.clock.now_millis();
+        if let Some(client) = self.get_client_mut(id) {
+            client.last_seen = now;
+        }
 
-            write
-                .client_ids
-                .remove(client_ids.iter().position(|x| x.0 == id).unwrap());
-            write.in_game = false;
-            write.abort_all_tickers(); // Nothing is functional with only one player, tickers don't need to be running
-            client_ids = write.client_ids.clone();
+        let packet_id = packet.header.id as usize;
+        let routed = self.room_handlers.contains(packet.header.id);
+        if !routed && !handlers.contains_key(packet_id) {
+            return;
+        }
 
-            // Drop write before room to prevent deadlock
-            drop(write);
+        if let Err(err) = self.authenticate(id, &packet) {
+            warn!("Authentication failed: {:?}", err);
+            self.send_error(id, packet.header.id, &err).await;
+            return;
+        }
 
-            if client_ids.len() >= 1 {
-                self.client_disconnected(&room, id).await;
+        // Rooms check their own guards and tell the client of their own errors
+        if routed {
+            if let Err(err) = InRoomGuard.guard(id, packet.clone(), self) {
+                warn!("Guard failed: {:?}", err);
+                self.send_error(id, packet.header.id, &err).await;
+                return;
             }
 
-            // Now we can drop the room
-            drop(room);
+            let room_id = self.get_client(id).unwrap().game_room_id;
+            self.send_room(room_id, RoomCommand::Packet(id, packet));
+            return;
+        }
 
-            if client_ids.len() == 1 {
-                // If there is still someone left, we have to worry about whether or not
-                // they need to be made the host of the room
-                self.handle_transfer_ownership(id, client_ids[0].0).await;
+        // Evaluate guards
+        if let Some(guard) = guards.get(packet_id).unwrap() {
+            if self.config.log_packet_output {
+                info!("Checking guard '{}'", guard.name());
             }
-        }
-    }
 
-    async fn handle_transfer_ownership(&mut self, leave: usize, stay: usize) {
-        if let Some(player) = &self.all_clients.get(&leave).unwrap().player {
-            // Host left the game, ownership needs to be transferred
-            if player.role == PlayerRole::One {
-                let client = self.all_clients.get_mut(&stay).unwrap();
-                client.player.as_mut().unwrap().role = PlayerRole::One;
+            if let Err(err) = guard.guard(id, packet.clone(), self) {
+                warn!("Guard failed: {:?}", err);
+                self.send_error(id, packet.header.id, &err).await;
+                return;
             }
         }
-    }
 
-    async fn handle_data(
-        &mut self,
-        id: usize,
-        packet: Packet,
-        handlers: &VecMap<PacketHandler>,
-        guards: &VecMap<Option<Box<dyn Guard>>>,
-    ) {
-        let packet_id = packet.header.id as usize;
-        if let Some(func) = handlers.get(packet_id) {
-            {
-                // Evaluate guards
-                if let Some(guard) = guards.get(packet_id).unwrap() {
-                    if self.config.log_packet_output {
-                        info!("Checking guard '{}'", guard.name());
-                    }
+        let func = handlers.get(packet_id).unwrap();
+        let res = func(self, id, packet.clone()).await;
+        if self.config.log_packet_output {
+            info!(
+                "Client {}: {} ==> {:?}",
+                id,
+                packet_name(Direction::Client, packet.header.id).unwrap_or("Unknown"),
+                res
+            );
+        }
+        if let Err(err) = &res {
+            self.send_error(id, packet.header.id, err).await;
+        }
+    }
 
-                    if let Err(err) = guard.guard(id, packet.clone(), &self) {
-                        warn!("Guard failed: {:?}", err);
-                        return;
-                    }
-                }
+    /// Checks that a packet was sent with the session token of the client, or with its id
+    /// for clients speaking a protocol from before `TOKEN_PROTOCOL_VERSION`, which includes
+    /// those that never send a hello
+    fn authenticate(&self, id: usize, packet: &Packet) -> Result<(), StratepigError> {
+        let data = BaseGuardPacket::deserialize(&packet.body)?;
+        let client = self.get_client(id).ok_or(StratepigError::MissingContext)?;
 
-                let res = func(self, id, packet.clone()).await;
-                if self.config.log_packet_output {
-                    info!(
-                        "Client {}: {:?} ==> {:?}",
-                        id,
-                        ClientMessage::from(packet.header.id),
-                        res
-                    );
-                }
-            }
+        if data.auth == client.session_token {
+            return Ok(());
+        }
+        let legacy = client
+            .protocol
+            .is_some_and(|x| x < version::TOKEN_PROTOCOL_VERSION);
+        if legacy && data.auth == id.to_string() {
+            return Ok(());
         }
+        Err(StratepigError::AssumeWrongId)
     }
 
     pub async fn message_one(&self, id: usize, packet: impl PacketBody) {
         if self.config.log_packet_output {
-            info!("OUTBOUND({}) => {:?}", id, ServerMessage::from(packet.id()));
+            info!(
+                "OUTBOUND({}) => {}",
+                id,
+                packet_name(Direction::Server, packet.id()).unwrap_or("Unknown")
+            );
         }
         if let Some(client) = self.get_client(id) {
-            let endpoint = client.endpoint;
-            self.handler.lock().network().send(
-                endpoint,
+            self.handler.network().send(
+                client.endpoint,
                 &stratepig_core::serialize_packet(Box::new(packet)).unwrap(),
             );
         }
     }
 
-    pub async fn message_room(&self, room: &GameRoom, packet: impl PacketBody) {
-        let client_ids = room.clients();
-        if self.config.log_packet_output {
-            info!(
-                "OUTBOUND({:?}) => {:?}",
-                client_ids,
-                ServerMessage::from(packet.id())
-            );
-        }
-
-        let bytes = &stratepig_core::serialize_packet(Box::new(packet)).unwrap();
-        for (id, _endpoint) in client_ids.into_iter() {
-            if let Some(client) = self.get_client(id) {
-                let endpoint = client.endpoint;
-                self.handler.lock().network().send(endpoint, bytes);
-            }
+    /// Settings of new rooms, before the host changes anything
+    pub fn default_settings(&self) -> GameRoomSettings {
+        let mut settings = GameRoomSettings::from_mode(self.modes.default_mode());
+        if self.config.one_player {
+            settings.bot = Some(Difficulty::Greedy);
         }
+        settings
     }
 
-    pub fn new_room(&mut self) -> Result<impl Deref<Target = GameRoom> + '_, &str> {
-        let mut game_rooms = self.game_rooms.lock();
-        if game_rooms.len() >= MAX_ROOMS {
+    /// Starts the task of a new room, returning its id
+    pub fn new_room(&mut self, settings: GameRoomSettings) -> Result<usize, &str> {
+        if self.game_rooms.len() >= self.config.max_rooms {
             return Err("There are too many rooms at the moment. Try again later.");
         }
 
-        let id = match self.free_game_room_ids.lock().pop_front() {
+        let id = match self.free_game_room_ids.pop_front() {
             Some(id) => id,
             None => {
-                let mut next = self.next_game_room_id.lock();
-                *next += 1;
-                *next
+                self.next_game_room_id += 1;
+                self.next_game_room_id
             }
         };
 
         let mut code = util::gen_game_room_code();
         // Ensure code is unique, as a 1/456976 chance is still possible
-        while self.game_room_codes.lock().contains(&code) {
+        while self.get_room_by_code(&code).is_some() {
             code = util::gen_game_room_code();
         }
 
-        let room = GameRoom::new(id, code.clone());
-        game_rooms.insert(id, room);
+        let context = RoomContext {
+            handler: self.handler.clone(),
+            outbox: Arc::new(self.handler.clone()),
+            config: self.config.clone(),
+            modes: self.modes.clone(),
+            clock: self.clock.clone(),
+            handlers: self.room_handlers.clone(),
+        };
+        let room = GameRoom::spawn(id, code.clone(), settings, context);
+        self.game_rooms.insert(id, room);
         trace!("New room '{}' created with ID {}", code, id);
-        Ok(MutexGuard::map(game_rooms, |g| g.get_mut(id).unwrap()))
+        Ok(id)
     }
 
-    pub fn get_room(&self, id: usize) -> Option<impl Deref<Target = GameRoom> + '_> {
-        let game_rooms = self.game_rooms.lock();
-        if game_rooms.get(id).is_none() {
-            return None;
-        }
-        Some(MutexGuard::map(game_rooms, |g| g.get_mut(id).unwrap()))
+    pub fn get_room(&self, id: usize) -> Option<&RoomHandle> {
+        self.game_rooms.get(id)
     }
 
-    pub fn get_room_by_code<'ret, 'me: 'ret>(
-        &'me self,
-        code: &str,
-    ) -> Option<impl Deref<Target = GameRoom> + 'ret> {
-        let mut game_rooms: MutexGuard<'ret, VecMap<GameRoom>> = self.game_rooms.lock();
-        for (id, room) in game_rooms.iter_mut() {
-            if room.inner().code == code {
-                return Some(MutexGuard::map(game_rooms, |g| g.get_mut(id).unwrap()));
-            }
+    pub fn get_room_by_code(&self, code: &str) -> Option<usize> {
+        self.game_rooms
+            .values()
+            .find(|room| room.code == code)
+            .map(|room| room.id)
+    }
+
+    /// Passes a command on to the task of a room, returning false if there is no such room
+    pub fn send_room(&self, room_id: usize, command: RoomCommand) -> bool {
+        self.get_room(room_id)
+            .is_some_and(|room| room.send(command))
+    }
+
+    /// Adds a client to a room, waiting for the room to accept them
+    pub async fn join_room(
+        &mut self,
+        room_id: usize,
+        id: usize,
+        username: String,
+        icon: u8,
+        hosting: bool,
+    ) -> Result<(), GameRoomError> {
+        let (reply, joined) = oneshot::channel();
+        let command = RoomCommand::Join {
+            id,
+            username,
+            icon,
+            hosting,
+            reply,
+        };
+        if !self.send_room(room_id, command) {
+            return Err(GameRoomError::NotFound);
         }
-        None
+
+        // A room that closed before getting to the command drops the reply
+        joined.await.unwrap_or(Err(GameRoomError::NotFound))?;
+        self.get_client_mut(id).unwrap().set_game_room(room_id);
+        Ok(())
     }
 
-    pub fn try_join_room(
-        &self,
-        code: &String,
-    ) -> Result<impl Deref<Target = GameRoom> + '_, GameRoomError> {
-        let room = self.get_room_by_code(code);
-        match room {
-            Some(room) => {
-                if room.inner().in_game {
-                    return Err(GameRoomError::Started);
-                } else if room.clients().len() >= 2 {
-                    return Err(GameRoomError::Full);
-                }
-                Ok(room)
+    /// Forgets a room whose task has ended, along with any client
+    /// that was waiting to resume a game in it
+    fn room_closed(&mut self, room_id: usize) {
+        let room = match self.game_rooms.remove(room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        self.free_game_room_ids.push_back(room_id);
+
+        let dropped: Vec<usize> = self
+            .all_clients
+            .values_mut()
+            .filter(|x| x.game_room_id == room_id)
+            .filter_map(|client| {
+                client.set_game_room(0);
+                client.reconnect_deadline.map(|_deadline| client.id)
+            })
+            .collect();
+        for id in dropped {
+            if let Some(ticker) = self.all_clients.remove(&id).unwrap().reconnect_ticker {
+                ticker.abort();
             }
-            None => Err(GameRoomError::NotFound),
         }
+
+        info!("Pruned room '{}' | ({})", room.code, self.game_rooms.len());
     }
 
     pub fn get_client(&self, id: usize) -> Option<&Client> {
         self.all_clients.get(&id)
     }
 
-    pub fn get_player(&self, id: usize) -> Option<&Player> {
-        self.get_client(id)?.player.as_ref()
-    }
-
     pub fn get_client_mut(&mut self, id: usize) -> Option<&mut Client> {
         self.all_clients.get_mut(&id)
     }
 
-    pub fn get_player_mut(&mut self, id: usize) -> Option<&mut Player> {
-        self.get_client_mut(id)?.player.as_mut()
-    }
-
-    pub fn get_context(&self, id: usize) -> Option<(&Client, impl Deref<Target = GameRoom> + '_)> {
-        let client = self.all_clients.get(&id).unwrap();
-        let room_id = client.game_room_id;
-        if room_id == 0 || client.room_player.is_none() {
-            return None;
-        }
-
-        let game_rooms = self.game_rooms.lock();
-        if let None = game_rooms.get(room_id) {
-            return None;
-        }
-
-        Some((
-            client,
-            MutexGuard::map(game_rooms, |g| g.get_mut(room_id).unwrap()),
-        ))
-    }
-
-    fn clone_guards(&self) -> VecMap<Option<Box<dyn Guard>>> {
+    fn clone_guards(&self) -> VecMap<Option<Box<dyn Guard<GameServer>>>> {
         let mut map = VecMap::new();
 
         for (id, guard) in self.guards.iter() {
@@ -430,112 +502,381 @@ impl GameServer {
         map
     }
 
-    fn run_prune_cycle(&mut self) {
-        let game_rooms = self.game_rooms.clone();
-        let free_game_room_ids = self.free_game_room_ids.clone();
+    fn run_prune_cycle(&self) {
         let handler = self.handler.clone();
-
-        thread::spawn(move || {
+        let clock = self.clock.clone();
+        let period = time::Duration::from_secs(self.config.prune_interval_secs);
+        tokio::task::spawn(async move {
             loop {
-                thread::sleep(time::Duration::from_secs(PRUNE_INTERVAL_SECS));
-
-                let now = util::unix_now();
+                clock.sleep(period).await;
+                handler.signals().send(ServerSignal::Prune);
+            }
+        });
+    }
+}
 
-                let mut to_prune = Vec::new();
-                for (id, room) in game_rooms.lock().iter_mut() {
-                    if !room.inner().in_game || room.inner().game_ended {
-                        if now > (room.inner().last_seen_at + MAX_PRUNE_AGE_SECS).into() {
-                            to_prune.push(id);
-                        }
-                    }
-                }
+/// Handlers of the packets that are passed on to the room of the client
+fn room_packet_handlers() -> RoomHandlers {
+    let mut handlers = RoomHandlers::default();
+
+    macro_rules! register_guarded {
+        ($id:expr, $p:expr, $g:expr) => {{
+            handlers
+                .handlers
+                .insert($id as usize, (|r, id, p| Box::pin($p(r, id, p))));
+            handlers.guards.insert($id as usize, Some(Box::new($g)));
+        }};
+    }
 
-                let mut pruned: usize = 0;
-                let mut game_rooms = game_rooms.lock();
-                for room_id in to_prune {
-                    // Inform each client that they were kicked
-                    let room = game_rooms.remove(room_id).unwrap();
-                    free_game_room_ids.lock().push_back(room_id);
-
-                    let packet = KickedPacket {
-                        msg: "Room closed due to inactivity.".to_owned(),
-                    };
-                    let bytes = &stratepig_core::serialize_packet(Box::new(packet)).unwrap();
-
-                    let handler = handler.lock();
-                    let endpoints: Vec<Endpoint> =
-                        room.clients().into_iter().map(|x| x.1).collect();
-                    for endpoint in endpoints.into_iter() {
-                        handler.network().send(endpoint, bytes);
-                    }
+    register_guarded!(
+        UpdateReadyStatePacket::ID,
+        GameRoom::handle_ready_state_change,
+        InRoomGuard
+    );
+    register_guarded!(
+        UpdatePigIconPacket::ID,
+        GameRoom::handle_update_icon,
+        InRoomGuard
+    );
+    register_guarded!(
+        UpdateSettingsValue::ID,
+        GameRoom::handle_settings_value_update,
+        InRoomGuard
+    );
+    register_guarded!(
+        UpdatePigItemValuePacket::ID,
+        GameRoom::handle_pig_item_update,
+        InRoomGuard
+    );
+    register_guarded!(
+        FinishedSceneLoadPacket::ID,
+        GameRoom::handle_client_finish_scene_load,
+        InRoomGuard
+    );
+
+    register_guarded!(
+        GamePlayerReadyDataDefaultPacket::ID,
+        GameRoom::handle_game_player_ready,
+        InGameGuard
+    );
+
+    register_guarded!(SurrenderPacket::ID, GameRoom::handle_surrender, InGameGuard);
+    register_guarded!(
+        LeaveGamePacket::ID,
+        GameRoom::handle_client_leave,
+        InGameGuard
+    );
+    register_guarded!(
+        PlayAgainPacket::ID,
+        GameRoom::handle_client_play_again,
+        InGameGuard
+    );
+    register_guarded!(MovePacket::ID, GameRoom::move_received, InGameStrictGuard);
+    register_guarded!(
+        LegalMovesRequestPacket::ID,
+        GameRoom::handle_legal_moves_request,
+        InGameStrictGuard
+    );
+
+    handlers
+}
 
-                    drop(room);
-                    pruned += 1;
-                }
+/// Starts relaying TLS connections to the plain TCP listener at `tcp_addr`
+async fn listen_tls(config: &CliConfig, tls_addr: &str, tcp_addr: SocketAddr) -> io::Result<()> {
+    let (cert, key) = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => (cert, key),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a certificate and key are required",
+            ))
+        }
+    };
+    let acceptor = tls::load_acceptor(cert, key)?;
+    let listener = tokio::net::TcpListener::bind(tls_addr).await?;
+    info!("Listening for Tls on {}", listener.local_addr()?);
 
-                info!("Pruned {} room(s) | ({})", pruned, game_rooms.len());
-            }
-        });
+    let mut upstream = tcp_addr;
+    if upstream.ip().is_unspecified() {
+        upstream.set_ip(Ipv4Addr::LOCALHOST.into());
     }
+    tokio::task::spawn(tls::run_proxy(listener, acceptor, upstream));
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() {
-    log_init::init();
+    let config = CliConfig::new();
+    log_init::init(config.as_ref().map_or(LevelFilter::Info, |x| x.log_level));
     info!("Starting Stratepig Server...");
 
-    let config = CliConfig::new();
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Failed to load config: {}", err);
+            std::process::exit(1);
+        }
+    };
     config.log();
 
-    let (handler, listener) = node::split::<()>();
-    handler
-        .network()
-        .listen(Transport::Tcp, "0.0.0.0:32500")
-        .unwrap();
-    let handler = Arc::new(Mutex::new(handler));
-
-    let mut server = GameServer {
-        handler,
-        config,
-        packet_handlers: VecMap::new(),
-        guards: VecMap::new(),
-        endpoints: Arc::new(Mutex::new(HashMap::new())),
-        all_clients: HashMap::new(),
-        next_client_id: 1,
-        free_client_ids: VecDeque::new(),
-        game_rooms: Arc::new(Mutex::new(VecMap::new())),
-        next_game_room_id: Arc::new(Mutex::new(0)),
-        free_game_room_ids: Arc::new(Mutex::new(VecDeque::new())),
-        game_room_codes: Arc::new(Mutex::new(Vec::new())),
+    let modes = match ModeRegistry::from_config(config.modes_dir.as_deref()) {
+        Ok(modes) => modes,
+        Err(err) => {
+            error!("Failed to load game modes: {}", err);
+            std::process::exit(1);
+        }
     };
 
-    let endpoints = server.endpoints.clone();
-    let game_rooms = server.game_rooms.clone();
+    let (handler, listener) = node::split::<ServerSignal>();
+    // Both transports feed the same handlers, so web and desktop clients can play each other
+    let mut transports = vec![(Transport::Tcp, config.tcp_addr.as_str())];
+    if let Some(ws_addr) = &config.ws_addr {
+        transports.push((Transport::Ws, ws_addr.as_str()));
+    }
+    let mut tcp_addr = None;
+    for (transport, addr) in transports {
+        match handler.network().listen(transport, addr) {
+            Ok((_id, addr)) => {
+                info!("Listening for {:?} on {}", transport, addr);
+                if transport == Transport::Tcp {
+                    tcp_addr = Some(addr);
+                }
+            }
+            Err(err) => {
+                error!("Failed to listen for {:?} on {}: {}", transport, addr, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(tls_addr) = &config.tls_addr {
+        if let Err(err) = listen_tls(&config, tls_addr, tcp_addr.unwrap()).await {
+            error!("Failed to listen for TLS on {}: {}", tls_addr, err);
+            std::process::exit(1);
+        }
+    }
 
+    let commands = handler.clone();
     thread::spawn(move || loop {
         let result = stratepig_cli::wait_for_command();
         if result.is_err() {
             continue;
         }
-        match result.unwrap().as_str() {
-            "ss stats" => {
-                let len_clients = endpoints.lock().len();
-                let len_game_rooms = game_rooms.lock().len();
-
-                println!("--- SERVER STATS ---");
-                println!("Number of clients: {}", len_clients);
-                println!("Number of rooms: {}", len_game_rooms);
-            }
-            _ => {}
+        if result.unwrap().as_str() == "ss stats" {
+            commands.signals().send(ServerSignal::Stats);
         }
     });
 
+    let mut server = GameServer::new(handler, config, modes, Arc::new(SystemClock));
+
     ctrlc::set_handler(|| {
         println!("Received exit signal");
         std::process::exit(0);
     })
     .expect("Error setting Ctrl-C handler");
 
-    server.register_packet_handlers();
     server.start(listener).await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::ErrorCode;
+    use gameroom::GameMode;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+    use stratepig_core::serialize_packet;
+    use stratepig_game::{generate_setup, SetupStrategy};
+
+    /// Starts a server on its own thread and returns the address it listens at
+    fn spawn_server(config: CliConfig) -> SocketAddr {
+        let (handler, listener) = node::split::<ServerSignal>();
+        let (_id, addr) = handler
+            .network()
+            .listen(Transport::Tcp, "127.0.0.1:0")
+            .unwrap();
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let modes = ModeRegistry::builtin().unwrap();
+                let mut server = GameServer::new(handler, config, modes, Arc::new(SystemClock));
+                server.start(listener).await;
+            });
+        });
+        addr
+    }
+
+    /// Reads packets until one with the given id, failing on any error packet
+    fn expect<P: PacketBody>(stream: &mut TcpStream, id: u8) -> P {
+        loop {
+            let mut header = [0; 3];
+            stream.read_exact(&mut header).unwrap();
+            let size = u16::from_le_bytes([header[0], header[1]]) as usize;
+            let mut body = vec![0; size];
+            stream.read_exact(&mut body).unwrap();
+
+            if header[2] == id {
+                return P::deserialize(&body).unwrap();
+            }
+            if id != ErrorPacket::ID && header[2] == ErrorPacket::ID {
+                let err = ErrorPacket::deserialize(&body).unwrap();
+                panic!("packet {} failed: {:?}", err.packet_id, err.detail);
+            }
+        }
+    }
+
+    /// A connection to the server, signing its packets with what it was welcomed with
+    struct TestClient {
+        stream: TcpStream,
+        welcome: WelcomePacket,
+    }
+
+    impl TestClient {
+        fn connect(addr: SocketAddr) -> Self {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let welcome = expect(&mut stream, WelcomePacket::ID);
+            Self { stream, welcome }
+        }
+
+        /// What clients from before session tokens sign their packets with
+        fn my_id(&self) -> String {
+            self.welcome.my_id.clone()
+        }
+
+        fn send(&mut self, packet: impl PacketBody + 'static) {
+            let bytes = serialize_packet(Box::new(packet)).unwrap();
+            self.stream.write_all(&bytes).unwrap();
+        }
+
+        fn expect<P: PacketBody>(&mut self, id: u8) -> P {
+            expect(&mut self.stream, id)
+        }
+
+        fn request_game(&mut self, auth: String, code: &str) -> GameInfoPacket {
+            self.send(GameRequestDefaultPacket {
+                auth,
+                is_hosting: code.is_empty(),
+                username: "pig".to_owned(),
+                icon: 0,
+                code: code.to_owned(),
+                data_null: true,
+            });
+            self.expect(GameInfoPacket::ID)
+        }
+    }
+
+    #[test]
+    fn clients_without_hello_join_rooms() {
+        let addr = spawn_server(CliConfig::default());
+
+        let mut host = TestClient::connect(addr);
+        let created = host.request_game(host.my_id(), "");
+
+        let mut guest = TestClient::connect(addr);
+        let joined = guest.request_game(guest.my_id(), &created.code);
+        assert_eq!(joined.code, created.code);
+
+        let added: RoomPlayerAddPacket = host.expect(RoomPlayerAddPacket::ID);
+        assert_eq!(added.client_count, 2);
+    }
+
+    #[test]
+    fn rejected_clients_cannot_join_rooms() {
+        let addr = spawn_server(CliConfig::default());
+
+        let mut client = TestClient::connect(addr);
+        let auth = client.welcome.session_token.clone();
+        client.send(HelloPacket {
+            auth: auth.clone(),
+            protocol: version::PROTOCOL_VERSION + 1,
+            build: "99.0.0".to_owned(),
+        });
+        let _rejected: VersionRejectedPacket = client.expect(VersionRejectedPacket::ID);
+        let err: ErrorPacket = client.expect(ErrorPacket::ID);
+        assert_eq!(err.packet_id, HelloPacket::ID);
+
+        client.send(GameRequestDefaultPacket {
+            auth,
+            is_hosting: true,
+            username: "pig".to_owned(),
+            icon: 0,
+            code: String::new(),
+            data_null: true,
+        });
+        let err: ErrorPacket = client.expect(ErrorPacket::ID);
+        assert_eq!(err.packet_id, GameRequestDefaultPacket::ID);
+        assert_eq!(err.code, ErrorCode::Incompatible as u32);
+    }
+
+    #[test]
+    fn legacy_clients_play_against_the_computer() {
+        let config = CliConfig {
+            one_player: true,
+            ..CliConfig::default()
+        };
+        let addr = spawn_server(config);
+
+        let mut client = TestClient::connect(addr);
+        let auth = client.my_id();
+        let info = client.request_game(auth.clone(), "");
+
+        client.send(UpdateReadyStatePacket {
+            auth: auth.clone(),
+            ready: true,
+        });
+        let _countdown: RoomTimerUpdatePacket = client.expect(RoomTimerUpdatePacket::ID);
+        client.send(FinishedSceneLoadPacket {
+            auth: auth.clone(),
+            scene_index: 2,
+        });
+        let _loaded: BothClientsLoadedGamePacket = client.expect(BothClientsLoadedGamePacket::ID);
+        // The countdown against the computer player takes a second
+        thread::sleep(Duration::from_millis(1500));
+
+        let modes = ModeRegistry::builtin().unwrap();
+        let mode = GameMode(info.game_mode as u8);
+        let settings = GameRoomSettings::from_mode(modes.get(mode).unwrap());
+        let setup = generate_setup(
+            &modes.layout(mode),
+            &settings.pig_config,
+            SetupStrategy::Random,
+            &mut rand::thread_rng(),
+        )
+        .unwrap();
+        client.send(GamePlayerReadyDataFullPacket {
+            auth: auth.clone(),
+            ready: true,
+            board: setup
+                .iter()
+                .map(|x| (x.pig as u32, x.location as u32))
+                .collect(),
+        });
+        let turn: TurnInitPacket = client.expect(TurnInitPacket::ID);
+        assert_eq!(turn.role, PlayerRole::One as u32);
+
+        let (from, to) = setup
+            .iter()
+            .find_map(|piece| {
+                client.send(LegalMovesRequestPacket {
+                    auth: auth.clone(),
+                    location: piece.location,
+                });
+                let moves: LegalMovesPacket = client.expect(LegalMovesPacket::ID);
+                moves.targets.first().map(|to| (moves.location, *to))
+            })
+            .unwrap();
+        client.send(MovePacket {
+            auth,
+            from_location: from,
+            to_location: to,
+        });
+
+        let moved: MoveDataPacket = client.expect(MoveDataPacket::ID);
+        assert_eq!(moved.role, PlayerRole::One as u32);
+        assert_eq!((moved.from, moved.to), (from, to));
+    }
+}
@@ -0,0 +1,387 @@
+use log::info;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::gameroom::{GameMode, SettingsGroup, SETTINGS_GROUPS};
+use stratepig_game::{flip_tile, Ability, BoardLayout, Pig, RepetitionRule, RuleSet};
+
+/// Modes shipped with the server, used when no mode directory is given
+const BUILTIN_MODES: [(&str, &str); 4] = [
+    ("original.toml", include_str!("../modes/original.toml")),
+    (
+        "infiltrator.toml",
+        include_str!("../modes/infiltrator.toml"),
+    ),
+    ("duel.toml", include_str!("../modes/duel.toml")),
+    ("lightning.toml", include_str!("../modes/lightning.toml")),
+];
+
+#[derive(Debug)]
+pub struct ModeError {
+    pub file: String,
+    pub msg: String,
+}
+
+impl ModeError {
+    fn new(file: &str, msg: impl Into<String>) -> Self {
+        Self {
+            file: file.to_owned(),
+            msg: msg.into(),
+        }
+    }
+}
+
+impl fmt::Display for ModeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid mode '{}': {}", self.file, self.msg)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LayoutDefinition {
+    width: u8,
+    height: u8,
+    #[serde(default)]
+    lakes: Vec<u8>,
+    setup_rows: u8,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+struct RulesDefinition {
+    scout_move_and_strike: bool,
+    single_use_bombs: bool,
+    attacker_wins_ties: bool,
+    spy_attack_only: bool,
+    chemist_ability: u32,
+    repetition: u32,
+}
+
+impl Default for RulesDefinition {
+    fn default() -> Self {
+        let rules = RuleSet::default();
+        Self {
+            scout_move_and_strike: rules.scout_move_and_strike,
+            single_use_bombs: rules.single_use_bombs,
+            attacker_wins_ties: rules.attacker_wins_ties,
+            spy_attack_only: rules.spy_attack_only,
+            chemist_ability: rules.chemist.map_or(0, |x| x as u32),
+            repetition: rules.repetition as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ModeFile {
+    id: u8,
+    name: String,
+    placement_time: u32,
+    turn_time: u32,
+    buffer_time: u32,
+    #[serde(default)]
+    rules: RulesDefinition,
+    layout: Option<LayoutDefinition>,
+    pigs: HashMap<String, u8>,
+}
+
+/// A validated game mode, as shown in the lobby settings
+#[derive(Debug, Clone)]
+pub struct ModeDefinition {
+    pub mode: GameMode,
+    pub name: String,
+    pub placement_time: u32,
+    pub turn_time: u32,
+    pub buffer_time: u32,
+    pub rules: RuleSet,
+    pub layout: BoardLayout,
+    pub pig_config: HashMap<Pig, u8>,
+}
+
+impl ModeDefinition {
+    pub fn parse(file: &str, contents: &str) -> Result<Self, ModeError> {
+        let data: ModeFile =
+            toml::from_str(contents).map_err(|e| ModeError::new(file, e.to_string()))?;
+
+        let mode = GameMode(data.id);
+        if mode.0 == 0 || mode == GameMode::CUSTOM {
+            return Err(ModeError::new(file, format!("reserved id {}", data.id)));
+        }
+
+        for (value, id) in [
+            (data.placement_time, 1),
+            (data.turn_time, 2),
+            (data.buffer_time, 3),
+        ]
+        .iter()
+        {
+            let group = SETTINGS_GROUPS.get(id).unwrap();
+            if !in_group(*value, group) {
+                return Err(ModeError::new(file, format!("time {} out of range", value)));
+            }
+        }
+        if data.rules.chemist_ability > Ability::MAX {
+            return Err(ModeError::new(file, "unknown chemist ability"));
+        }
+        if data.rules.repetition > RepetitionRule::MAX {
+            return Err(ModeError::new(file, "unknown repetition rule"));
+        }
+
+        let layout = match data.layout {
+            Some(layout) => BoardLayout {
+                width: layout.width,
+                height: layout.height,
+                lakes: layout.lakes,
+                setup_rows: layout.setup_rows,
+            },
+            None => BoardLayout::standard(),
+        };
+        validate_layout(&layout).map_err(|msg| ModeError::new(file, msg))?;
+
+        let mut pig_config: HashMap<Pig, u8> = (0..13).map(|i| (Pig::from(i), 0)).collect();
+        for (name, amount) in data.pigs {
+            let pig = Pig::from_name(&name)
+                .ok_or_else(|| ModeError::new(file, format!("unknown pig '{}'", name)))?;
+            pig_config.insert(pig, amount);
+        }
+
+        if pig_config[&Pig::Flag] != 1 {
+            return Err(ModeError::new(file, "exactly one flag is required"));
+        }
+        let total: u32 = pig_config.values().map(|x| *x as u32).sum();
+        if total > layout.setup_tiles() as u32 {
+            return Err(ModeError::new(file, "too many pigs for the setup rows"));
+        }
+
+        Ok(Self {
+            mode,
+            name: data.name,
+            placement_time: data.placement_time,
+            turn_time: data.turn_time,
+            buffer_time: data.buffer_time,
+            rules: RuleSet {
+                scout_move_and_strike: data.rules.scout_move_and_strike,
+                single_use_bombs: data.rules.single_use_bombs,
+                attacker_wins_ties: data.rules.attacker_wins_ties,
+                spy_attack_only: data.rules.spy_attack_only,
+                chemist: Ability::from(data.rules.chemist_ability),
+                repetition: RepetitionRule::from(data.rules.repetition),
+            },
+            layout,
+            pig_config,
+        })
+    }
+}
+
+fn in_group(value: u32, group: &SettingsGroup) -> bool {
+    value as i32 >= group.min_val
+        && value as i32 <= group.max_val
+        && value.is_multiple_of(group.interval)
+}
+
+fn validate_layout(layout: &BoardLayout) -> Result<(), String> {
+    let tiles = layout.width as u32 * layout.height as u32;
+    if layout.width < 2 || tiles > u8::MAX as u32 {
+        return Err("board size out of range".to_owned());
+    }
+    if layout.setup_rows == 0 || u32::from(layout.setup_rows) * 2 > u32::from(layout.height) {
+        return Err("setup rows do not fit on the board".to_owned());
+    }
+    for lake in layout.lakes.iter() {
+        if *lake == 0
+            || *lake as u32 > tiles
+            || *lake <= layout.setup_tiles()
+            || !layout.is_lake(flip_tile(layout, *lake))
+        {
+            return Err(format!("lake at {} is invalid", lake));
+        }
+    }
+    Ok(())
+}
+
+/// All game modes available to rooms, ordered by id
+#[derive(Debug)]
+pub struct ModeRegistry {
+    modes: Vec<ModeDefinition>,
+}
+
+impl ModeRegistry {
+    pub fn new(mut modes: Vec<ModeDefinition>) -> Result<Self, ModeError> {
+        if modes.is_empty() {
+            return Err(ModeError::new("*", "no modes defined"));
+        }
+
+        let mut ids = HashSet::new();
+        for def in modes.iter() {
+            if !ids.insert(def.mode) {
+                return Err(ModeError::new(&def.name, "duplicate id"));
+            }
+        }
+
+        modes.sort_by_key(|x| x.mode.0);
+        Ok(Self { modes })
+    }
+
+    pub fn builtin() -> Result<Self, ModeError> {
+        let modes = BUILTIN_MODES
+            .iter()
+            .map(|(file, contents)| ModeDefinition::parse(file, contents))
+            .collect::<Result<_, _>>()?;
+        Self::new(modes)
+    }
+
+    /// Loads every `.toml` file in `dir`
+    pub fn load(dir: &Path) -> Result<Self, ModeError> {
+        let dir_name = dir.display().to_string();
+        let entries = fs::read_dir(dir).map_err(|e| ModeError::new(&dir_name, e.to_string()))?;
+
+        let mut modes = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| ModeError::new(&dir_name, e.to_string()))?
+                .path();
+            if path.extension().is_none_or(|x| x != "toml") {
+                continue;
+            }
+
+            let file = path.display().to_string();
+            let contents =
+                fs::read_to_string(&path).map_err(|e| ModeError::new(&file, e.to_string()))?;
+            modes.push(ModeDefinition::parse(&file, &contents)?);
+        }
+
+        Self::new(modes)
+    }
+
+    /// Loads the mode directory if given, otherwise the built in modes
+    pub fn from_config(dir: Option<&str>) -> Result<Self, ModeError> {
+        let registry = match dir {
+            Some(dir) => Self::load(Path::new(dir))?,
+            None => Self::builtin()?,
+        };
+        for def in registry.modes.iter() {
+            info!("Loaded mode {} '{}'", def.mode.0, def.name);
+        }
+        Ok(registry)
+    }
+
+    pub fn get(&self, mode: GameMode) -> Option<&ModeDefinition> {
+        self.modes.iter().find(|x| x.mode == mode)
+    }
+
+    /// The first mode by id, used for new rooms
+    pub fn default_mode(&self) -> &ModeDefinition {
+        &self.modes[0]
+    }
+
+    /// Layout of a mode, where custom and unknown modes use the standard board
+    pub fn layout(&self, mode: GameMode) -> BoardLayout {
+        match self.get(mode) {
+            Some(def) => def.layout.clone(),
+            None => BoardLayout::standard(),
+        }
+    }
+
+    /// Steps through the loaded modes followed by the custom mode, wrapping around
+    pub fn cycle(&self, mode: GameMode, forward: bool) -> GameMode {
+        let mut order: Vec<GameMode> = self.modes.iter().map(|x| x.mode).collect();
+        order.push(GameMode::CUSTOM);
+        order.sort_by_key(|x| x.0);
+
+        let index = order.iter().position(|x| *x == mode).unwrap_or(0);
+        let next = if forward {
+            (index + 1) % order.len()
+        } else {
+            (index + order.len() - 1) % order.len()
+        };
+        order[next]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r#"
+        id = 7
+        name = "Test"
+        placement_time = 120
+        turn_time = 10
+        buffer_time = 60
+
+        [pigs]
+        flag = 1
+        scout = 3
+    "#;
+
+    #[test]
+    fn builtin_modes_are_valid() {
+        let registry = ModeRegistry::builtin().unwrap();
+        assert_eq!(registry.default_mode().mode, GameMode(1));
+        // Modes with chemists give them an ability
+        for def in registry.modes.iter() {
+            let chemists = def.pig_config[&Pig::Chemist] > 0;
+            assert_eq!(def.rules.chemist.is_some(), chemists, "{}", def.name);
+        }
+
+        let lightning = registry.get(GameMode(5)).unwrap();
+        assert_eq!(lightning.layout, BoardLayout::lightning());
+        assert_eq!(registry.layout(GameMode::CUSTOM), BoardLayout::standard());
+    }
+
+    #[test]
+    fn cycles_through_custom() {
+        let registry = ModeRegistry::builtin().unwrap();
+        assert_eq!(registry.cycle(GameMode(3), true), GameMode::CUSTOM);
+        assert_eq!(registry.cycle(GameMode::CUSTOM, true), GameMode(5));
+        assert_eq!(registry.cycle(GameMode(5), true), GameMode(1));
+        assert_eq!(registry.cycle(GameMode(1), false), GameMode(5));
+    }
+
+    #[test]
+    fn validates_modes() {
+        let def = ModeDefinition::parse("test", VALID).unwrap();
+        assert_eq!(def.pig_config[&Pig::Scout], 3);
+        assert_eq!(def.pig_config[&Pig::Bomb], 0);
+        assert_eq!(def.rules, RuleSet::default());
+
+        let rules = format!("{}\n[rules]\nattacker_wins_ties = true", VALID);
+        let def = ModeDefinition::parse("test", &rules).unwrap();
+        assert!(def.rules.attacker_wins_ties);
+        assert!(def.rules.spy_attack_only);
+        let chemist = format!("{}\n[rules]\nchemist_ability = 2", VALID);
+        let def = ModeDefinition::parse("test", &chemist).unwrap();
+        assert_eq!(def.rules.chemist, Some(Ability::ConvertTie));
+        let chemist = format!("{}\n[rules]\nchemist_ability = 3", VALID);
+        assert!(ModeDefinition::parse("test", &chemist).is_err());
+        let repetition = format!("{}\n[rules]\nrepetition = 3", VALID);
+        assert!(ModeDefinition::parse("test", &repetition).is_err());
+
+        let no_flag = VALID.replace("flag = 1", "flag = 0");
+        assert!(ModeDefinition::parse("test", &no_flag).is_err());
+        let unknown = VALID.replace("scout", "knight");
+        assert!(ModeDefinition::parse("test", &unknown).is_err());
+        let reserved = VALID.replace("id = 7", "id = 4");
+        assert!(ModeDefinition::parse("test", &reserved).is_err());
+        let slow = VALID.replace("turn_time = 10", "turn_time = 45");
+        assert!(ModeDefinition::parse("test", &slow).is_err());
+        let crowded = VALID.replace("scout = 3", "scout = 40");
+        assert!(ModeDefinition::parse("test", &crowded).is_err());
+
+        let lakes = format!(
+            "{}\n[layout]\nwidth = 8\nheight = 8\nlakes = [27]\nsetup_rows = 3",
+            VALID
+        );
+        assert!(ModeDefinition::parse("test", &lakes).is_err());
+        let deep = format!(
+            "{}\n[layout]\nwidth = 2\nheight = 100\nlakes = []\nsetup_rows = 128",
+            VALID
+        );
+        assert!(ModeDefinition::parse("test", &deep).is_err());
+
+        let def = ModeDefinition::parse("test", VALID).unwrap();
+        assert!(ModeRegistry::new(vec![def.clone(), def]).is_err());
+    }
+}
@@ -1,20 +1,25 @@
 use serde::{Deserialize, Serialize};
 
-use crate::stratepig_macros::{client_packet, server_packet};
-use crate::PacketBody;
-use stratepig_core;
+use stratepig_macros::{client_packet, server_packet};
 
 ////////////////////////////////////////
 ////// SERVER PACKETS //////////////////
 ////////////////////////////////////////
 
 #[server_packet(0)]
-pub struct KeepAlivePacket;
+pub struct KeepAlivePacket {
+    pub timestamp: u128,
+    /// Last measured round trip time of the receiving client in milliseconds
+    pub rtt: u32,
+}
 
 #[server_packet(1)]
 pub struct WelcomePacket {
     pub version: String,
-    pub my_id: String, // TODO: use usize eventually
+    /// Clients from before session tokens send this back to authenticate their packets
+    pub my_id: String,
+    pub session_token: String,
+    pub protocol: u32,
 }
 
 #[server_packet(2)]
@@ -64,6 +69,19 @@ pub struct GameInfoPacket {
     pub turn_time: u32,
     pub buffer_time: u32,
     pub pig_config: Vec<(u32, u32)>,
+    // Fields below were added later, so they go after the ones older clients read
+    pub chemist_ability: u32,
+    pub board_width: u8,
+    pub board_height: u8,
+    pub lakes: Vec<u8>,
+    pub setup_rows: u8,
+    pub scout_move_and_strike: bool,
+    pub single_use_bombs: bool,
+    pub attacker_wins_ties: bool,
+    pub spy_attack_only: bool,
+    pub repetition: u32,
+    pub bot: u32,
+    pub forfeit_placement: bool,
 }
 
 #[server_packet(10)]
@@ -100,6 +118,13 @@ pub struct RoomTimerUpdatePacket {
 #[server_packet(15)]
 pub struct BothClientsLoadedGamePacket;
 
+#[server_packet(16)]
+pub struct GameTimerUpdatePacket {
+    pub timestamp: u128,
+    pub server_now: u128,
+    pub remaining: u32,
+}
+
 #[server_packet(17)]
 pub struct GamePlayerUpdatedReadyStatePacket {
     pub id: String,
@@ -119,7 +144,7 @@ pub struct MoveDataPacket {
     pub bundle_null: bool,
 }
 
-#[server_packet(19)]
+#[server_packet(19, variant)]
 pub struct MoveDataAttackPacket {
     pub role: u32,
     pub from: u8,
@@ -128,6 +153,21 @@ pub struct MoveDataAttackPacket {
     pub result: i32,
     pub init_type: u32,
     pub target_type: u32,
+    pub ability: u32,
+}
+
+#[server_packet(19, variant)]
+pub struct MoveDataInfiltratePacket {
+    pub role: u32,
+    pub from: u8,
+    pub to: u8,
+    pub bundle_null: bool,
+    pub result: i32,
+    pub init_type: u32,
+    pub target_type: u32,
+    pub ability: u32,
+    pub guess: u32,
+    pub correct: bool,
 }
 
 #[server_packet(20)]
@@ -161,18 +201,76 @@ pub struct ClientPlayAgainPacket {
     pub id: String,
 }
 
+#[server_packet(25)]
+pub struct LegalMovesPacket {
+    pub location: u8,
+    /// Every tile the pig at `location` can move to or attack
+    pub targets: Vec<u8>,
+}
+
+#[server_packet(26)]
+pub struct MoveRejectedPacket {
+    pub from: u8,
+    pub to: u8,
+    pub msg: String,
+}
+
+#[server_packet(27)]
+pub struct AutoPlacementPacket {
+    pub board: Vec<(u32, u32)>,
+}
+
+#[server_packet(28)]
+pub struct GameResyncPacket {
+    pub my_id: String,
+    pub game_phase: u8,
+    pub ready: bool,
+    pub opponent_ready: bool,
+    pub current_turn: u32,
+    pub board: Vec<(u32, u32)>,
+    /// Pigs not yet revealed are sent as -1
+    pub enemy_board: Vec<(i32, u32)>,
+    pub timer_timestamp: u128,
+    pub timer_is_buffer: bool,
+    pub server_now: u128,
+}
+
+#[server_packet(29)]
+pub struct ClientReconnectedPacket {
+    pub id: String,
+}
+
+#[server_packet(30)]
+pub struct VersionRejectedPacket {
+    pub reason: u32,
+    pub min_protocol: u32,
+    pub min_version: String,
+    pub msg: String,
+    pub update_hint: String,
+}
+
+#[server_packet(31)]
+pub struct ErrorPacket {
+    pub code: u32,
+    /// Id of the client packet that failed
+    pub packet_id: u8,
+    pub detail: Option<String>,
+}
+
 ////////////////////////////////////////
 ////// CLIENT PACKETS //////////////////
 ////////////////////////////////////////
 
+/// The start of every client packet, which is checked before the packet is handled
 #[client_packet(0)]
 pub struct BaseGuardPacket {
-    pub my_id: String,
+    /// The session token, or the client id for clients older than `TOKEN_PROTOCOL_VERSION`
+    pub auth: String,
 }
 
 #[client_packet(1)]
 pub struct GameRequestDefaultPacket {
-    pub my_id: String,
+    pub auth: String,
     pub is_hosting: bool,
     pub username: String,
     pub icon: i32,
@@ -180,9 +278,9 @@ pub struct GameRequestDefaultPacket {
     pub data_null: bool,
 }
 
-#[client_packet(1)]
+#[client_packet(1, variant)]
 pub struct GameRequestFullPacket {
-    pub my_id: String,
+    pub auth: String,
     pub is_hosting: bool,
     pub username: String,
     pub icon: i32,
@@ -197,161 +295,201 @@ pub struct GameRequestFullPacket {
 
 #[client_packet(2)]
 pub struct UpdateReadyStatePacket {
-    pub my_id: String,
+    pub auth: String,
     pub ready: bool,
 }
 
 #[client_packet(3)]
 pub struct UpdatePigIconPacket {
-    pub my_id: String,
+    pub auth: String,
     pub icon: u32,
 }
 
 #[client_packet(4)]
 pub struct UpdateSettingsValue {
-    pub my_id: String,
+    pub auth: String,
     pub settings_id: u32,
     pub increased: bool,
 }
 
 #[client_packet(5)]
 pub struct UpdatePigItemValuePacket {
-    pub my_id: String,
+    pub auth: String,
     pub pig: u32,
     pub increased: bool,
 }
 
 #[client_packet(6)]
 pub struct FinishedSceneLoadPacket {
-    pub my_id: String,
+    pub auth: String,
     pub scene_index: u32,
 }
 
 #[client_packet(7)]
 pub struct GamePlayerReadyDataDefaultPacket {
-    pub my_id: String,
+    pub auth: String,
     pub ready: bool,
 }
 
-#[client_packet(7)]
+#[client_packet(7, variant)]
 pub struct GamePlayerReadyDataFullPacket {
-    pub my_id: String,
+    pub auth: String,
     pub ready: bool,
     pub board: Vec<(u32, u32)>,
 }
 
 #[client_packet(8)]
 pub struct MovePacket {
-    pub my_id: String,
+    pub auth: String,
+    pub from_location: u8,
+    pub to_location: u8,
+}
+
+#[client_packet(8, variant)]
+pub struct MoveInfiltratePacket {
+    pub auth: String,
     pub from_location: u8,
     pub to_location: u8,
+    pub guess: u32,
 }
 
 // Useless packets
+#[allow(dead_code)]
 #[client_packet(9)]
 pub struct SurrenderPacket;
+#[allow(dead_code)]
 #[client_packet(10)]
 pub struct LeaveGamePacket;
+#[allow(dead_code)]
 #[client_packet(11)]
 pub struct PlayAgainPacket;
 
-#[allow(dead_code)]
-#[derive(Debug)]
-/// Messages that the server can send to the client
-pub enum ServerMessage {
-    KeepAlive = 0,
-    Welcome = 1,
-    Kicked = 2,
-    ClientDisconnect = 3,
-    RoomPlayerAdd = 4,
-    RoomPlayerUpdatedReadyState = 5,
-    FailCreateGame = 6,
-    ErrorJoinGame = 7,
-    ClientInfo = 8,
-    GameInfo = 9,
-    UpdatedPigIcon = 10,
-    SettingsValueChanged = 11,
-    PigItemValueChanged = 12,
-    PigConfigValueChanged = 13,
-    RoomTimerUpdate = 14,
-    BothClientsLoadedGame = 15,
-    GameTimerUpdate = 16,
-    GamePlayerUpdatedReadyState = 17,
-    OpponentPigPlacement = 18,
-    MoveData = 19,
-    TurnInit = 20,
-    TurnSecondUpdate = 21,
-    Win = 22,
-    EnemyPieceData = 23,
-    ClientPlayAgain = 24,
-    Null,
-}
-
-impl ServerMessage {
-    pub fn from(id: u8) -> Self {
-        match id {
-            1 => Self::Welcome,
-            2 => Self::Kicked,
-            3 => Self::ClientDisconnect,
-            4 => Self::RoomPlayerAdd,
-            5 => Self::RoomPlayerUpdatedReadyState,
-            6 => Self::FailCreateGame,
-            7 => Self::ErrorJoinGame,
-            8 => Self::ClientInfo,
-            9 => Self::GameInfo,
-            10 => Self::UpdatedPigIcon,
-            11 => Self::SettingsValueChanged,
-            12 => Self::PigItemValueChanged,
-            13 => Self::PigConfigValueChanged,
-            14 => Self::RoomTimerUpdate,
-            15 => Self::BothClientsLoadedGame,
-            16 => Self::GameTimerUpdate,
-            17 => Self::GamePlayerUpdatedReadyState,
-            18 => Self::OpponentPigPlacement,
-            19 => Self::MoveData,
-            20 => Self::TurnInit,
-            21 => Self::TurnSecondUpdate,
-            22 => Self::Win,
-            23 => Self::EnemyPieceData,
-            24 => Self::ClientPlayAgain,
-            _ => Self::Null,
+/// Asks for the moves of a selected pig, to highlight them on the board
+#[client_packet(12)]
+pub struct LegalMovesRequestPacket {
+    pub auth: String,
+    pub location: u8,
+}
+
+#[client_packet(13)]
+pub struct ResumePacket {
+    pub auth: String,
+    pub session_token: String,
+}
+
+#[client_packet(14)]
+pub struct KeepAliveEchoPacket {
+    pub auth: String,
+    /// The timestamp of the keep alive being echoed
+    pub timestamp: u128,
+}
+
+#[client_packet(15)]
+pub struct HelloPacket {
+    pub auth: String,
+    pub protocol: u32,
+    pub build: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use stratepig_core::{packet_name, registered_packets, Direction, PacketBody};
+
+    #[test]
+    fn packets_round_trip() {
+        let packet = MoveRejectedPacket {
+            from: 12,
+            to: 22,
+            msg: "pig found in between to and from locations".to_owned(),
+        };
+        let data = PacketBody::serialize(&packet).unwrap();
+        let read = <MoveRejectedPacket as PacketBody>::deserialize(&data).unwrap();
+        assert_eq!((read.from, read.to, read.msg), (12, 22, packet.msg));
+
+        let packet = HelloPacket {
+            auth: "token".to_owned(),
+            protocol: 3,
+            build: "0.8.0".to_owned(),
+        };
+        let data = PacketBody::serialize(&packet).unwrap();
+        let read = <HelloPacket as PacketBody>::deserialize(&data).unwrap();
+        assert_eq!(read.auth, "token");
+        assert_eq!(read.protocol, 3);
+        assert_eq!(packet.id(), HelloPacket::ID);
+    }
+
+    #[test]
+    fn game_info_keeps_old_layout() {
+        // The fields clients knew before the packet grew
+        #[derive(Deserialize)]
+        struct OldGameInfoPacket {
+            code: String,
+            game_mode: i32,
+            placement_time: u32,
+            turn_time: u32,
+            buffer_time: u32,
+            pig_config: Vec<(u32, u32)>,
         }
+
+        let packet = GameInfoPacket {
+            code: "ABCD".to_owned(),
+            game_mode: 1,
+            placement_time: 300,
+            turn_time: 15,
+            buffer_time: 300,
+            pig_config: vec![(0, 6), (3, 1)],
+            chemist_ability: 2,
+            board_width: 10,
+            board_height: 10,
+            lakes: vec![42, 43],
+            setup_rows: 4,
+            scout_move_and_strike: true,
+            single_use_bombs: false,
+            attacker_wins_ties: false,
+            spy_attack_only: true,
+            repetition: 1,
+            bot: 0,
+            forfeit_placement: false,
+        };
+        let data = PacketBody::serialize(&packet).unwrap();
+        let old: OldGameInfoPacket = bincode::deserialize(&data).unwrap();
+        assert_eq!(old.code, "ABCD");
+        assert_eq!(
+            (
+                old.game_mode,
+                old.placement_time,
+                old.turn_time,
+                old.buffer_time
+            ),
+            (1, 300, 15, 300)
+        );
+        assert_eq!(old.pig_config, packet.pig_config);
     }
-}
 
-#[allow(dead_code)]
-#[derive(Debug)]
-/// Messages the client can send to the server
-pub enum ClientMessage {
-    GameRequestSent = 1,
-    UpdateReadyState = 2,
-    UpdatePigIcon = 3,
-    UpdateSettingsValue = 4,
-    UpdatePigItemValue = 5,
-    FinishedSceneLoad = 6,
-    GamePlayerReadyData = 7,
-    Move = 8,
-    Surrender = 9,
-    LeaveGame = 10,
-    PlayAgain = 11,
-    Null,
-}
-
-impl ClientMessage {
-    pub fn from(id: u8) -> Self {
-        match id {
-            1 => Self::GameRequestSent,
-            2 => Self::UpdateReadyState,
-            3 => Self::UpdatePigIcon,
-            4 => Self::UpdateSettingsValue,
-            5 => Self::UpdatePigItemValue,
-            6 => Self::FinishedSceneLoad,
-            7 => Self::GamePlayerReadyData,
-            8 => Self::Move,
-            9 => Self::Surrender,
-            10 => Self::LeaveGame,
-            11 => Self::PlayAgain,
-            _ => Self::Null,
+    #[test]
+    fn registry_has_unique_ids() {
+        let mut seen = HashSet::new();
+        for info in registered_packets().filter(|x| !x.variant) {
+            assert!(
+                seen.insert((info.direction, info.id)),
+                "{} reused",
+                info.name
+            );
+        }
+
+        // Every variant shares its id with a packet
+        for info in registered_packets().filter(|x| x.variant) {
+            assert!(seen.contains(&(info.direction, info.id)), "{}", info.name);
         }
+
+        assert_eq!(packet_name(Direction::Server, 19), Some("MoveDataPacket"));
+        assert_eq!(packet_name(Direction::Client, 8), Some("MovePacket"));
+        assert_eq!(
+            packet_name(Direction::Server, ErrorPacket::ID),
+            Some("ErrorPacket")
+        );
+        assert_eq!(packet_name(Direction::Client, 200), None);
     }
 }
@@ -1,7 +1,7 @@
-use crate::client::Client;
-
 use stratepig_game::{Board, Piece};
 
+pub use stratepig_game::PlayerRole;
+
 #[derive(Debug)]
 pub struct Player {
     pub role: PlayerRole,
@@ -16,23 +16,6 @@ pub struct Player {
     pub init_board: Board,
 }
 
-#[derive(PartialEq, Copy, Clone, Debug)]
-pub enum PlayerRole {
-    One = 1,
-    Two = 2,
-    Tie = -1,
-}
-
-impl PlayerRole {
-    pub fn opp(&self) -> Self {
-        match self {
-            Self::One => Self::Two,
-            Self::Two => Self::One,
-            _ => Self::Tie,
-        }
-    }
-}
-
 impl Player {
     /// Constructs a new player instance given a role
     pub fn new(role: PlayerRole) -> Self {
@@ -69,8 +52,7 @@ pub struct RoomPlayer {
 
 impl RoomPlayer {
     /// Constructs a new room player instance
-    pub fn new(role: PlayerRole, username: String, icon: u8, client: &mut Client) -> Self {
-        client.set_player(Player::new(role));
+    pub fn new(username: String, icon: u8) -> Self {
         Self {
             username,
             ready: false,
@@ -82,3 +64,28 @@ impl RoomPlayer {
         self.ready = false;
     }
 }
+
+/// A client in a room, as the room sees them
+pub struct Member {
+    pub id: usize,
+    pub room_player: RoomPlayer,
+    pub player: Player,
+    /// Whether their connection dropped, with the client able to resume
+    pub dropped: bool,
+}
+
+impl Member {
+    pub fn new(id: usize, role: PlayerRole, room_player: RoomPlayer) -> Self {
+        Self {
+            id,
+            room_player,
+            player: Player::new(role),
+            dropped: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.room_player.reset();
+        self.player.reset();
+    }
+}
@@ -0,0 +1,71 @@
+use log::info;
+use std::time::Duration;
+use stratepig_core::{Packet, PacketBody};
+
+use crate::packet::{KeepAliveEchoPacket, KeepAlivePacket};
+use crate::GameServer;
+use crate::ServerSignal;
+use crate::StratepigError;
+
+impl GameServer {
+    pub fn run_heartbeat_cycle(&self) {
+        let handler = self.handler.clone();
+        let clock = self.clock.clone();
+        let interval = Duration::from_secs(self.config.heartbeat_interval_secs);
+        tokio::task::spawn(async move {
+            loop {
+                clock.sleep(interval).await;
+                handler.signals().send(ServerSignal::Heartbeat);
+            }
+        });
+    }
+
+    /// Disconnects every client that has not sent anything within the idle timeout,
+    /// then sends a keep alive to the rest for them to echo back
+    pub async fn heartbeat(&mut self) {
+        let now = self.clock.now_millis();
+        let timeout = self.config.idle_timeout_secs as u128 * 1000;
+
+        let (idle, alive): (Vec<_>, Vec<_>) = self
+            .all_clients
+            .values()
+            .filter(|x| x.reconnect_deadline.is_none())
+            .map(|x| (x.id, x.endpoint, x.last_seen))
+            .partition(|(_id, _endpoint, last_seen)| now.saturating_sub(*last_seen) > timeout);
+
+        for (id, endpoint, _last_seen) in idle {
+            info!("Client {} timed out", id);
+            // Removing the connection does not raise a disconnect event
+            self.handler.network().remove(endpoint.resource_id());
+            self.handle_disconnect(endpoint).await;
+        }
+
+        for (id, _endpoint, _last_seen) in alive {
+            let rtt = self.get_client(id).and_then(|x| x.rtt_ms).unwrap_or(0);
+            let packet = KeepAlivePacket {
+                timestamp: now,
+                rtt,
+            };
+            self.message_one(id, packet).await;
+        }
+    }
+
+    /// Measures the round trip time from the echo of a keep alive
+    pub async fn handle_keep_alive(
+        &mut self,
+        id: usize,
+        packet: Packet,
+    ) -> Result<(), StratepigError> {
+        let data = KeepAliveEchoPacket::deserialize(&packet.body)?;
+
+        let rtt = self.clock.now_millis().saturating_sub(data.timestamp) as u32;
+        if self.config.log_packet_output {
+            info!("Client {} round trip: {}ms", id, rtt);
+        }
+        if let Some(client) = self.get_client_mut(id) {
+            client.rtt_ms = Some(rtt);
+        }
+
+        Ok(())
+    }
+}
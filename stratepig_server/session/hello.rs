@@ -0,0 +1,35 @@
+use log::info;
+use stratepig_core::{Packet, PacketBody};
+
+use crate::error::ErrorCode;
+use crate::packet::HelloPacket;
+use crate::version;
+use crate::GameServer;
+use crate::StratepigError;
+
+impl GameServer {
+    /// Checks the protocol version of a client before it can play
+    pub async fn handle_hello(&mut self, id: usize, packet: Packet) -> Result<(), StratepigError> {
+        let data = HelloPacket::deserialize(&packet.body)?;
+
+        if let Err(reason) = version::check(data.protocol) {
+            info!(
+                "Rejected client {} with protocol {} (build {})",
+                id, data.protocol, data.build
+            );
+            if let Some(client) = self.get_client_mut(id) {
+                client.protocol = None;
+            }
+            self.version_rejected(id, reason).await;
+            return Err(StratepigError::coded(
+                ErrorCode::Incompatible,
+                "incompatible protocol version",
+            ));
+        }
+
+        if let Some(client) = self.get_client_mut(id) {
+            client.protocol = Some(data.protocol);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::Duration;
+use stratepig_core::{Packet, PacketBody};
+use tokio::sync::oneshot;
+
+use crate::error::ErrorCode;
+use crate::gameroom::{GameRoom, RoomCommand};
+use crate::packet::ResumePacket;
+use crate::win::WinType;
+use crate::GameServer;
+use crate::ServerSignal;
+use crate::StratepigError;
+
+mod heartbeat;
+mod hello;
+mod send;
+
+impl GameServer {
+    /// Tells the room of a client that their connection dropped. If they are in a game,
+    /// they are given some time to resume before they lose, and true is returned.
+    pub async fn client_dropped(&mut self, room_id: usize, id: usize) -> bool {
+        let grace = Duration::from_secs(self.config.reconnect_grace_secs);
+        let deadline = self.clock.millis_in(grace);
+        let (reply, kept) = oneshot::channel();
+        let command = RoomCommand::Disconnected {
+            id,
+            deadline,
+            reply,
+        };
+        if !self.send_room(room_id, command) || !kept.await.unwrap_or(false) {
+            return false;
+        }
+
+        let handler = self.handler.clone();
+        let sleep = self.clock.sleep(grace);
+        let ticker = tokio::task::spawn(async move {
+            sleep.await;
+            handler.signals().send(ServerSignal::ReconnectExpired(id));
+        });
+
+        let client = self.get_client_mut(id).unwrap();
+        client.reconnect_deadline = Some(deadline);
+        client.reconnect_ticker = Some(ticker);
+        true
+    }
+
+    /// Makes a client that did not come back in time lose, then removes them
+    pub async fn reconnect_expired(&mut self, id: usize) {
+        let room_id = match self.get_client(id) {
+            Some(client) if client.reconnect_deadline.is_some() => client.game_room_id,
+            _ => return,
+        };
+
+        self.send_room(room_id, RoomCommand::ReconnectExpired(id));
+        self.all_clients.remove(&id);
+    }
+
+    /// Attaches a new connection to the client of a dropped connection with the same
+    /// session token, then lets their room send everything needed to continue the game
+    pub async fn handle_resume(&mut self, id: usize, packet: Packet) -> Result<(), StratepigError> {
+        let data = ResumePacket::deserialize(&packet.body)?;
+
+        let old_id = self
+            .all_clients
+            .values()
+            .find(|x| x.reconnect_deadline.is_some() && x.session_token == data.session_token)
+            .map(|x| x.id);
+        let old_id = match old_id {
+            Some(old_id) => old_id,
+            None => {
+                self.resume_failed(id).await;
+                return Err(StratepigError::coded(
+                    ErrorCode::NoSession,
+                    "no session to resume",
+                ));
+            }
+        };
+
+        // The new connection takes over the old client
+        let endpoint = self.all_clients.remove(&id).unwrap().endpoint;
+        self.endpoints.insert(endpoint, old_id);
+
+        let client = self.get_client_mut(old_id).unwrap();
+        client.endpoint = endpoint;
+        client.reconnect_deadline = None;
+        if let Some(t) = client.reconnect_ticker.take() {
+            t.abort();
+        }
+        let room_id = client.game_room_id;
+
+        self.send_room(room_id, RoomCommand::Resume(old_id));
+        Ok(())
+    }
+}
+
+impl GameRoom {
+    /// Keeps a client whose connection dropped during a game, pausing the timers
+    /// while they can still resume. Anywhere else they are removed from the room.
+    pub async fn disconnected(&mut self, id: usize, deadline: u128) -> bool {
+        if !self.in_game || self.game_ended || self.member(id).is_none() {
+            self.remove_member(id).await;
+            return false;
+        }
+
+        self.member_mut(id).unwrap().dropped = true;
+        self.client_reconnecting(id, deadline).await;
+        self.pause_timers();
+        true
+    }
+
+    /// Sends a resumed client everything needed to continue their game,
+    /// restarting the timers if nobody else is still away
+    pub async fn resume(&mut self, id: usize) {
+        let member = match self.member_mut(id) {
+            Some(member) => member,
+            None => return,
+        };
+        member.dropped = false;
+        let role = member.player.role;
+
+        self.initialize_player(id, role).await;
+        self.send_game_info(Some(id)).await;
+        self.send_resync(id).await;
+        self.client_reconnected(id).await;
+
+        if !self.members.iter().any(|x| x.dropped) {
+            self.resume_timers().await;
+        }
+    }
+
+    /// Ends a running game in favour of the opponent of a client that left
+    pub async fn forfeit_disconnected(&mut self, id: usize) {
+        if !self.in_game || self.game_ended {
+            return;
+        }
+        let role = match self.player(id) {
+            Some(player) => player.role,
+            None => return,
+        };
+
+        self.game_ended = true;
+        self.broadcast_win(role.opp(), WinType::Disconnect).await;
+    }
+
+    /// Stops the placement or turn timer, taking off any buffer time used so far
+    /// as the turn starts over once everyone is back
+    fn pause_timers(&mut self) {
+        if self.paused_at.is_some() {
+            return;
+        }
+        self.abort_game_ticker();
+        self.paused_at = Some(self.clock.now_millis());
+
+        let buffer_start = self.last_buffer_timestamp.take();
+        let active_id = self.get_active_id();
+        if let (Some(timestamp), Some(id)) = (buffer_start, active_id) {
+            let diff = ((self.clock.now_millis() - timestamp) as f32 / 1000.0).ceil() as u128;
+            let player = self.player_mut(id).unwrap();
+            player.current_buffer = player.current_buffer.saturating_sub(diff);
+        }
+    }
+
+    /// Restarts the timers stopped by `pause_timers`, with placement
+    /// continuing from the time that was left
+    async fn resume_timers(&mut self) {
+        let paused_at = match self.paused_at.take() {
+            Some(paused_at) => paused_at,
+            None => return,
+        };
+        if self.game_ended {
+            return;
+        }
+
+        if self.game_phase == 1 {
+            if let Some(deadline) = self.timer_deadline {
+                let remaining = (deadline.saturating_sub(paused_at) as f32 / 1000.0).ceil();
+                self.start_placement(remaining as u32).await;
+            }
+        } else if !self.config.ignore_turns {
+            self.start_player_turn(false).await;
+        }
+    }
+}
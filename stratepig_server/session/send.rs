@@ -0,0 +1,108 @@
+use crate::*;
+
+/// Tells a client why its packet was not handled
+fn error_packet(packet_id: u8, err: &StratepigError) -> ErrorPacket {
+    ErrorPacket {
+        code: err.code() as u32,
+        packet_id,
+        detail: err.detail(),
+    }
+}
+
+impl GameServer {
+    pub async fn version_rejected(&self, id: usize, reason: version::Incompatibility) {
+        let msg = match reason {
+            version::Incompatibility::Outdated => format!(
+                "This version of Stratepig is no longer supported, {} or newer is required.",
+                version::min_build()
+            ),
+            version::Incompatibility::Unknown => format!(
+                "This version of Stratepig is newer than the server ({}).",
+                version::VERSION
+            ),
+        };
+        let packet = VersionRejectedPacket {
+            reason: reason as u32,
+            min_protocol: version::min_protocol(),
+            min_version: version::min_build().to_owned(),
+            msg,
+            update_hint: version::UPDATE_HINT.to_owned(),
+        };
+        self.message_one(id, packet).await;
+    }
+
+    pub async fn send_error(&self, id: usize, packet_id: u8, err: &StratepigError) {
+        self.message_one(id, error_packet(packet_id, err)).await;
+    }
+
+    pub async fn resume_failed(&self, id: usize) {
+        let packet = ErrJoinGamePacket {
+            msg: "Your game could not be resumed.".to_owned(),
+        };
+        self.message_one(id, packet).await;
+    }
+}
+
+impl GameRoom {
+    pub async fn send_error(&self, id: usize, packet_id: u8, err: &StratepigError) {
+        self.message_one(id, error_packet(packet_id, err)).await;
+    }
+
+    pub async fn client_reconnecting(&self, id: usize, deadline: u128) {
+        let packet = ClientDisconnectPacket {
+            id: id.to_string(),
+            timestamp: deadline as u64,
+        };
+        self.message_room(packet).await;
+    }
+
+    pub async fn client_reconnected(&self, id: usize) {
+        let packet = ClientReconnectedPacket { id: id.to_string() };
+        self.message_room(packet).await;
+    }
+
+    /// Sends a resumed client their board, what they know of the enemy board,
+    /// and the current turn and timer
+    pub async fn send_resync(&self, id: usize) {
+        let player = match self.player(id) {
+            Some(player) => player,
+            None => return,
+        };
+        let opponent = match self.bot.as_ref() {
+            Some(bot) => Some(bot),
+            None => self.other_member(id).map(|x| &x.player),
+        };
+
+        let board = player
+            .board
+            .iter()
+            .map(|x| (x.pig as u32, x.location as u32))
+            .collect();
+        // Enemy pigs are only placed on the board once the game begins
+        let enemy_board = match opponent {
+            Some(opponent) if self.game_phase == 2 => opponent
+                .board
+                .iter()
+                .map(|x| {
+                    let pig = if x.revealed { x.pig as i32 } else { -1 };
+                    (pig, x.location as u32)
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let packet = GameResyncPacket {
+            my_id: id.to_string(),
+            game_phase: self.game_phase,
+            ready: player.is_ready,
+            opponent_ready: opponent.is_some_and(|x| x.is_ready),
+            current_turn: self.current_turn as u32,
+            board,
+            enemy_board,
+            timer_timestamp: self.timer_deadline.unwrap_or(0),
+            timer_is_buffer: self.timer_is_buffer,
+            server_now: self.clock.now_millis(),
+        };
+        self.message_one(id, packet).await;
+    }
+}
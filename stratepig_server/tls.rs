@@ -0,0 +1,124 @@
+//! Optional TLS listener. message-io has no TLS transport, so connections are
+//! decrypted here and relayed to the plain TCP listener on this machine.
+
+use log::{trace, warn};
+use rustls_pemfile::{certs, private_key};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+/// Builds an acceptor from PEM encoded certificate chain and private key files
+pub fn load_acceptor(cert_path: &str, key_path: &str) -> io::Result<TlsAcceptor> {
+    let cert_chain =
+        certs(&mut BufReader::new(File::open(cert_path)?)).collect::<Result<Vec<_>, _>>()?;
+    let key = private_key(&mut BufReader::new(File::open(key_path)?))?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no private key found"))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Accepts TLS connections forever, relaying each one to `upstream`
+pub async fn run_proxy(listener: TcpListener, acceptor: TlsAcceptor, upstream: SocketAddr) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!("Failed to accept TLS connection: {}", err);
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        tokio::task::spawn(async move {
+            if let Err(err) = relay(stream, acceptor, upstream).await {
+                trace!("TLS connection from {} closed: {}", addr, err);
+            }
+        });
+    }
+}
+
+async fn relay(stream: TcpStream, acceptor: TlsAcceptor, upstream: SocketAddr) -> io::Result<()> {
+    let mut tls = acceptor.accept(stream).await?;
+    let mut plain = TcpStream::connect(upstream).await?;
+    tokio::io::copy_bidirectional(&mut tls, &mut plain).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    /// Writes a self-signed certificate for localhost, returning the paths and the certificate
+    fn self_signed(name: &str) -> (String, String, Vec<u8>) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let dir = std::env::temp_dir();
+        let cert_path = dir.join(format!("stratepig-{}-cert.pem", name));
+        let key_path = dir.join(format!("stratepig-{}-key.pem", name));
+        std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+        std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+
+        (
+            cert_path.to_string_lossy().into_owned(),
+            key_path.to_string_lossy().into_owned(),
+            cert.cert.der().to_vec(),
+        )
+    }
+
+    #[tokio::test]
+    async fn relays_packets() {
+        let (cert_path, key_path, der) = self_signed("relay");
+        let acceptor = load_acceptor(&cert_path, &key_path).unwrap();
+
+        // Stands in for the plain TCP listener, echoing whatever arrives
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+        tokio::task::spawn(async move {
+            let (mut stream, _addr) = upstream.accept().await.unwrap();
+            let (mut read, mut write) = stream.split();
+            tokio::io::copy(&mut read, &mut write).await.unwrap();
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::task::spawn(run_proxy(listener, acceptor, upstream_addr));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(der.into()).unwrap();
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(config));
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut tls = connector
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+
+        // A header and a two byte body
+        let packet = [2, 0, 13, 0, 0];
+        tls.write_all(&packet).await.unwrap();
+        let mut echoed = [0; 5];
+        tls.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(echoed, packet);
+    }
+
+    #[test]
+    fn rejects_missing_keys() {
+        let (cert_path, _key_path, _der) = self_signed("missing");
+        assert!(load_acceptor(&cert_path, &cert_path).is_err());
+        assert!(load_acceptor(&cert_path, "/nonexistent/key.pem").is_err());
+    }
+}
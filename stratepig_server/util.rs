@@ -1,6 +1,5 @@
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use std::time::{Duration, SystemTime};
 
 pub fn gen_game_room_code() -> String {
     let mut rng = thread_rng();
@@ -13,25 +12,12 @@ pub fn gen_game_room_code() -> String {
     output.to_uppercase()
 }
 
-pub fn unix_now() -> u128 {
-    SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_millis()
-}
-
-pub fn unix_now_secs() -> u64 {
-    SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-}
-
-pub fn unix_timestamp_to(to: Duration) -> u128 {
-    let then = SystemTime::now() + to;
-    then.duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_millis()
+pub fn gen_session_token() -> String {
+    thread_rng()
+        .sample_iter(Alphanumeric)
+        .map(char::from)
+        .take(32)
+        .collect()
 }
 
 #[macro_export]
@@ -54,4 +40,12 @@ mod tests {
             println!("{}", gen_game_room_code());
         }
     }
+
+    #[test]
+    fn session_tokens() {
+        let token = gen_session_token();
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|x| x.is_ascii_alphanumeric()));
+        assert_ne!(token, gen_session_token());
+    }
 }
@@ -1 +1,78 @@
-pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Version of the packet protocol, raised whenever packets change in a way
+/// older clients cannot understand
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// First protocol version sending the session token instead of the client id
+pub const TOKEN_PROTOCOL_VERSION: u32 = 3;
+
+/// Protocol version of clients that never send a hello, since they came before it
+pub const LEGACY_PROTOCOL_VERSION: u32 = 2;
+
+/// Protocol versions the server still speaks, with the oldest client build using each
+const COMPATIBLE: &[(u32, &str)] = &[
+    (LEGACY_PROTOCOL_VERSION, "0.7.0"),
+    (PROTOCOL_VERSION, "0.8.0"),
+];
+
+pub const UPDATE_HINT: &str = "Please update Stratepig to the latest version.";
+
+/// Why the protocol version of a client was rejected
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Incompatibility {
+    /// The client is older than anything the server speaks
+    Outdated = 1,
+    /// The client is newer than the server
+    Unknown = 2,
+}
+
+pub fn check(protocol: u32) -> Result<(), Incompatibility> {
+    if COMPATIBLE
+        .iter()
+        .any(|(version, _build)| *version == protocol)
+    {
+        Ok(())
+    } else if protocol < min_protocol() {
+        Err(Incompatibility::Outdated)
+    } else {
+        Err(Incompatibility::Unknown)
+    }
+}
+
+/// Protocol version of a client until it sends a hello,
+/// or `None` once the server no longer speaks the legacy protocol
+pub fn initial_protocol() -> Option<u32> {
+    check(LEGACY_PROTOCOL_VERSION)
+        .ok()
+        .map(|()| LEGACY_PROTOCOL_VERSION)
+}
+
+pub fn min_protocol() -> u32 {
+    COMPATIBLE.iter().map(|x| x.0).min().unwrap()
+}
+
+/// Oldest client build the server can play with
+pub fn min_build() -> &'static str {
+    COMPATIBLE
+        .iter()
+        .min_by_key(|(version, _build)| *version)
+        .unwrap()
+        .1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_protocols() {
+        assert_eq!(check(PROTOCOL_VERSION), Ok(()));
+        assert_eq!(check(TOKEN_PROTOCOL_VERSION - 1), Ok(()));
+        assert_eq!(initial_protocol(), Some(LEGACY_PROTOCOL_VERSION));
+        assert_eq!(check(1), Err(Incompatibility::Outdated));
+        assert_eq!(check(PROTOCOL_VERSION + 1), Err(Incompatibility::Unknown));
+        assert!(min_protocol() <= PROTOCOL_VERSION);
+        assert_eq!(min_build(), "0.7.0");
+    }
+}
@@ -1,3 +1,5 @@
+use stratepig_game::WinReason;
+
 #[derive(Copy, Clone)]
 pub enum WinType {
     FlagCapture = 1,
@@ -20,3 +22,12 @@ impl WinType {
         }
     }
 }
+
+impl From<WinReason> for WinType {
+    fn from(reason: WinReason) -> Self {
+        match reason {
+            WinReason::FlagCapture => Self::FlagCapture,
+            WinReason::OutOfMoves => Self::OutOfMoves,
+        }
+    }
+}